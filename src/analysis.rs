@@ -0,0 +1,276 @@
+use super::Position;
+use super::lev::{ Direction, Level, ObjectType };
+use super::rec::{ EventType, Frame, Player, Replay };
+use super::time::{ Time, FRAME_DURATION };
+
+/// Default `air_gap` for `kinematics`. Ground touch events are only recorded for impacts, so
+/// gaps of a few hundred milliseconds are common while driving on the ground.
+pub const DEFAULT_AIR_GAP: Time = Time(1000);
+
+/// Movement of the bike during a replay.
+#[derive(Debug, Default, PartialEq)]
+pub struct Kinematics {
+    /// Bike velocity in units per second, where `velocity[n]` is from frame `n` to `n + 1`.
+    /// Replays don't record the velocity at the first frame, so there is one less than frames.
+    pub velocity: Vec<Position<f64>>,
+    /// Bike speed in units per second, for each velocity.
+    pub speed: Vec<f64>,
+    /// Bike acceleration in units per second squared, where `acceleration[n]` is from
+    /// `velocity[n]` to `velocity[n + 1]`.
+    pub acceleration: Vec<Position<f64>>,
+    /// Total distance travelled by the bike.
+    pub distance: f64,
+    /// Highest speed.
+    pub top_speed: f64,
+    /// Distance divided by the recorded time.
+    pub average_speed: f64,
+    /// Start and end times of intervals spent in the air.
+    pub air: Vec<(Time, Time)>,
+    /// Time spent in the air.
+    pub air_time: Time,
+    /// Time spent on the ground.
+    pub ground_time: Time
+}
+
+/// Calculates speed, distance and air time of a player in a replay.
+///
+/// Replays don't record when the bike leaves the ground, so air time is estimated from ground
+/// touch events: gaps longer than `air_gap` between two ground touches, or between the start or
+/// end of the replay and the nearest ground touch, count as air time. The bike can also roll
+/// without impacts, so long gaps may still be ground time. `DEFAULT_AIR_GAP` is a good start.
+///
+/// # Examples
+///
+/// ```
+/// use elma::analysis::{ kinematics, DEFAULT_AIR_GAP };
+/// use elma::rec::{ Replay, Player };
+/// let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
+/// let kinematics = kinematics(&replay, Player::One, DEFAULT_AIR_GAP);
+/// assert_eq!(kinematics.speed.len(), replay.frames.len() - 1);
+/// assert!(kinematics.top_speed >= kinematics.average_speed);
+/// ```
+pub fn kinematics (replay: &Replay, player: Player, air_gap: Time) -> Kinematics {
+    let frames = replay.frames_of(player);
+    let seconds = FRAME_DURATION / 1000.;
+
+    // Differences between consecutive values, divided by frame duration.
+    let derivative = |values: &[Position<f64>]| -> Vec<Position<f64>> {
+        values.windows(2).map(|pair| Position {
+            x: (pair[1].x - pair[0].x) / seconds,
+            y: (pair[1].y - pair[0].y) / seconds
+        }).collect()
+    };
+    let positions: Vec<_> = frames.iter().map(|frame| Position { x: f64::from(frame.bike.x), y: f64::from(frame.bike.y) }).collect();
+    let velocity = derivative(&positions);
+    let acceleration = derivative(&velocity);
+    let speed: Vec<_> = velocity.iter().map(|velocity| velocity.x.hypot(velocity.y)).collect();
+    let distance = speed.iter().sum::<f64>() * seconds;
+
+    let duration = recorded_time(frames);
+    let air = air_intervals(replay, player, air_gap, duration);
+    let air_time = air.iter().fold(Time::default(), |total, &(start, end)| total + (end - start));
+
+    Kinematics {
+        top_speed: speed.iter().cloned().fold(0., f64::max),
+        average_speed: if duration.ms() > 0 { distance / (duration.ms() as f64 / 1000.) } else { 0. },
+        velocity,
+        speed,
+        acceleration,
+        distance,
+        air,
+        air_time,
+        ground_time: duration - air_time
+    }
+}
+
+/// Recorded time of frames, like `Replay::get_time_ms` gives for unfinished replays.
+fn recorded_time (frames: &[Frame]) -> Time {
+    Time::from_frames(frames.len())
+}
+
+/// Intervals longer than `air_gap` between ground touch events, and the start and end of the
+/// replay at `duration`. Intervals are cut off at `duration`.
+fn air_intervals (replay: &Replay, player: Player, air_gap: Time, duration: Time) -> Vec<(Time, Time)> {
+    let mut touches = vec![Time::default()];
+    touches.extend(replay.events_of(player)
+                         .iter()
+                         .filter(|event| matches!(event.event_type, EventType::Ground { .. }))
+                         .map(|event| Time::from_event_time(event.time)));
+    if touches[touches.len() - 1] < duration {
+        touches.push(duration);
+    }
+    touches.windows(2)
+           .filter(|touches| touches[1] - touches[0] > air_gap && touches[0] < duration)
+           .map(|touches| (touches[0], touches[1].min(duration)))
+           .collect()
+}
+
+/// Keys pressed by a player during a replay.
+#[derive(Debug, Default, PartialEq)]
+pub struct Inputs {
+    /// Start and end times of intervals with throttle pressed, from frames.
+    pub throttle: Vec<(Time, Time)>,
+    /// Times of turns, and whether the bike faces right after turning.
+    pub turns: Vec<(Time, bool)>,
+    /// Times of right volts.
+    pub volts_right: Vec<Time>,
+    /// Times of left volts.
+    pub volts_left: Vec<Time>,
+    /// Recorded time.
+    pub duration: Time
+}
+
+impl Inputs {
+    /// Number of turns.
+    pub fn turn_count (&self) -> usize {
+        self.turns.len()
+    }
+
+    /// Number of volts in both directions.
+    pub fn volt_count (&self) -> usize {
+        self.volts_right.len() + self.volts_left.len()
+    }
+
+    /// Percentage of recorded time with throttle pressed.
+    pub fn throttle_percentage (&self) -> f64 {
+        if self.duration.ms() <= 0 { return 0. }
+        let throttle = self.throttle.iter().fold(Time::default(), |total, &(start, end)| total + (end - start));
+        throttle.ms() as f64 / self.duration.ms() as f64 * 100.
+    }
+}
+
+/// Rebuilds the inputs of a player from frame flags and events. Throttle has frame accuracy,
+/// while turns and volts have exact event times. Turn directions are read from the frame
+/// flags after each turn.
+///
+/// # Examples
+///
+/// ```
+/// use elma::rec::{ Replay, Player };
+/// let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
+/// let inputs = elma::analysis::inputs(&replay, Player::One);
+/// assert!(inputs.throttle_percentage() > 50.);
+/// ```
+pub fn inputs (replay: &Replay, player: Player) -> Inputs {
+    let frames = replay.frames_of(player);
+    let mut inputs = Inputs { duration: recorded_time(frames), ..Inputs::default() };
+
+    let mut start = None;
+    for (n, frame) in frames.iter().enumerate() {
+        match (frame.throttle(), start) {
+            (true, None) => start = Some(Time::from_frames(n)),
+            (false, Some(time)) => {
+                inputs.throttle.push((time, Time::from_frames(n)));
+                start = None;
+            },
+            _ => ()
+        }
+    }
+    if let Some(time) = start {
+        inputs.throttle.push((time, inputs.duration));
+    }
+
+    let mut right = frames.first().is_some_and(|frame| frame.right());
+    for event in replay.events_of(player) {
+        let time = Time::from_event_time(event.time);
+        match event.event_type {
+            EventType::Turn => {
+                // The direction flag changes in the first frame after the turn. Turns after the
+                // last frame can only flip the previous direction.
+                right = match frames.get(event.frame().max(0.).floor() as usize + 1) {
+                    Some(frame) => frame.right(),
+                    None => !right
+                };
+                inputs.turns.push((time, right));
+            },
+            EventType::VoltRight => inputs.volts_right.push(time),
+            EventType::VoltLeft => inputs.volts_left.push(time),
+            _ => ()
+        }
+    }
+    inputs
+}
+
+/// Apple taken during a replay.
+#[derive(Debug, PartialEq)]
+pub struct Apple {
+    /// Player who took the apple.
+    pub player: Player,
+    /// Object index in the level.
+    pub object: usize,
+    /// Apple position.
+    pub position: Position<f64>,
+    /// Time the apple was taken.
+    pub time: Time,
+    /// Time since the previous apple, or since the start for the first apple.
+    pub split: Time
+}
+
+/// Gravity change from taking a gravity apple.
+#[derive(Debug, PartialEq)]
+pub struct GravityChange {
+    /// Player whose gravity changed.
+    pub player: Player,
+    /// Object index of the apple.
+    pub object: usize,
+    /// Time of the change.
+    pub time: Time,
+    /// New gravity direction.
+    pub gravity: Direction
+}
+
+/// Apples taken during a replay, in order.
+#[derive(Debug, Default, PartialEq)]
+pub struct AppleTimeline {
+    /// Apples taken by either player. An apple only counts the first time it's touched.
+    pub apples: Vec<Apple>,
+    /// Gravity changes.
+    pub gravity: Vec<GravityChange>
+}
+
+/// Lists apples taken in a replay of `level`, with split times and gravity changes.
+///
+/// # Examples
+///
+/// ```
+/// use elma::lev::{ Level, Object, ObjectType };
+/// let replay = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
+/// let mut level = Level::new();
+/// level.objects = vec![Object::new(), Object::new(), Object::new(), Object::new()];
+/// level.objects[0].object_type = ObjectType::Player;
+/// level.objects[3].object_type = ObjectType::Exit;
+/// let timeline = elma::analysis::apple_timeline(&replay, &level);
+/// assert_eq!(timeline.apples.len(), 2);
+/// assert_eq!(timeline.apples[1].object, 2);
+/// ```
+pub fn apple_timeline (replay: &Replay, level: &Level) -> AppleTimeline {
+    let mut touches: Vec<_> = [Player::One, Player::Two].iter().flat_map(|&player| {
+        replay.events_of(player).iter().filter_map(move |event| match event.event_type {
+            EventType::Touch { index } if index >= 0 => Some((Time::from_event_time(event.time), player, index as usize)),
+            _ => None
+        })
+    }).collect();
+    // Stable sort keeps player one first for touches at the same time.
+    touches.sort_by_key(|&(time, _, _)| time);
+
+    let mut timeline = AppleTimeline::default();
+    let mut previous = Time::default();
+    for (time, player, index) in touches {
+        let object = match level.objects.get(index) {
+            Some(object) => object,
+            None => continue
+        };
+        let gravity = match object.object_type {
+            ObjectType::Apple { gravity, .. } => gravity,
+            _ => continue
+        };
+        if timeline.apples.iter().any(|apple| apple.object == index) { continue }
+
+        timeline.apples.push(Apple { player, object: index, position: object.position, time, split: time - previous });
+        previous = time;
+        if gravity != Direction::Normal {
+            timeline.gravity.push(GravityChange { player, object: index, time, gravity });
+        }
+    }
+    timeline
+}
@@ -1,15 +1,39 @@
+use std::error;
+use std::fmt;
 use std::io::{ Read, Write };
 use std::fs::File;
 use std::path::Path;
 use std::cmp::Ordering;
+use std::collections::{ BTreeSet, HashSet };
+use std::convert::TryFrom;
 use byteorder::{ ByteOrder, ReadBytesExt, WriteBytesExt, LittleEndian };
-use rand::random;
-use super::{ Position, trim_string, string_null_pad, EOD, EOF, EMPTY_TOP10, ElmaError, OBJECT_RADIUS };
+use super::{ Position, ParseContext, checked_split_at, split_items, trim_string, string_null_pad, EOD, EOF, EMPTY_TOP10, ElmaError, RandomSource, ThreadRandom, ZeroRng, HEAD_RADIUS, OBJECT_RADIUS };
+use super::lgr::{ Lgr, PictureType };
+use super::time::Time;
+pub use super::Version;
+
+// Sum of the last integrity sum and the level sum in Across levels. It is outside the Elma
+// range, and the only real Across level we have (tests/assets/levels/across.lev) stores
+// exactly this value, so Across levels are written with it instead of a random one. With a
+// single sample, the range Across accepts is unknown, and saved Across levels are not known
+// to load in the game.
+const ACROSS_INTEGRITY_3: f64 = 28298.;
+
+// Estimated head position relative to the player start object, which is the left wheel. Taken
+// from the first frame of tests/assets/replays/test_1.rec, where the left wheel is at
+// (-0.850, -0.524) and the head at (0, 0.439) relative to the bike, assuming level y
+// coordinates point down. The topology status the editor saved in the test levels doesn't
+// confirm it, so heads and apples in ground are only reported as warnings.
+const HEAD_OFFSET: Position<f64> = Position { x: 0.85, y: -0.963 };
+
+// Average number of grid cells an edge may span in the intersection check. Long edges fill
+// many cells each, so above this the edges are swept instead.
+const MAX_CELLS_PER_EDGE: usize = 8;
 
 /// Topology related errors.
 #[derive(Debug, PartialEq)]
 pub enum TopologyError {
-    /// Apple is fully inside ground, with list of erroneous objects' indexes.
+    /// Apple is fully inside ground, with list of erroneous objects' indexes. Only a warning.
     AppleInsideGround(Vec<usize>),
     /// Intersecting polygons, with list of erroneous polygons' indexes.
     IntersectingPolygons(Vec<usize>),
@@ -29,25 +53,99 @@ pub enum TopologyError {
     TooWide(f64),
     /// Level is too high, with excess height.
     TooHigh(f64),
+    /// Head of the player may start inside ground. Only a warning, since the head position is
+    /// estimated.
+    HeadInsideGround,
+    /// Picture, texture or mask name not found in LGR, with list of erroneous pictures' indexes.
+    UnknownPictures(Vec<usize>),
+    /// Texture without a mask, with list of erroneous pictures' indexes.
+    MissingMask(Vec<usize>),
+    /// Picture distance outside 1..999, with list of erroneous pictures' indexes.
+    InvalidPictureDistance(Vec<usize>),
 }
 
-/// This trait specifies something having a rectangle bounding box.
-pub trait BoundingBox {
-    /// Bounding box of `&self`, going from top-left, top-right, bottom-left to bottom-right.
-    fn bounding_box(&self) -> [Position<f64>;4];
+impl fmt::Display for TopologyError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TopologyError::AppleInsideGround(ref objects) => write!(f, "apples fully inside ground: {:?}", objects),
+            TopologyError::IntersectingPolygons(ref polygons) => write!(f, "intersecting polygons: {:?}", polygons),
+            TopologyError::InvalidVertexCount(ref polygons) => write!(f, "polygons with too few vertices: {:?}", polygons),
+            TopologyError::MaxObjects(excess) => write!(f, "{} objects too many", excess),
+            TopologyError::MaxPictures(excess) => write!(f, "{} pictures too many", excess),
+            TopologyError::MaxPolygons(excess) => write!(f, "{} polygons too many", excess),
+            TopologyError::InvalidPlayerCount(count) => write!(f, "{} players, expected exactly one", count),
+            TopologyError::MissingExit => write!(f, "missing exit"),
+            TopologyError::TooWide(excess) => write!(f, "level is {} units too wide", excess),
+            TopologyError::TooHigh(excess) => write!(f, "level is {} units too high", excess),
+            TopologyError::HeadInsideGround => write!(f, "head of the player starts inside ground"),
+            TopologyError::UnknownPictures(ref pictures) => write!(f, "pictures not found in LGR: {:?}", pictures),
+            TopologyError::MissingMask(ref pictures) => write!(f, "textures without a mask: {:?}", pictures),
+            TopologyError::InvalidPictureDistance(ref pictures) => write!(f, "pictures with invalid distance: {:?}", pictures),
+        }
+    }
+}
+
+impl error::Error for TopologyError {}
+
+/// Severity of a topology finding.
+#[derive(Debug, PartialEq)]
+pub enum Severity {
+    /// Level will not load or cannot be played.
+    Error,
+    /// Level loads, but probably not as intended.
+    Warning
+}
+
+/// Topology state stored in level integrity sums when the level was saved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TopologyStatus {
+    /// Saved as valid, and can be played.
+    Ok,
+    /// Saved with topology errors, and will not load in the game.
+    Error
 }
 
-/// Game version.
+/// Location of a topology finding.
 #[derive(Debug, PartialEq)]
-pub enum Version {
-    /// Action SuperCross, older version of Elma.
-    Across,
-    /// Elasto Mania, current active version.
-    Elma
+pub enum Location {
+    /// Level as a whole.
+    Level,
+    /// Polygon, with polygon index.
+    Polygon(usize),
+    /// Polygon vertex, or the edge starting at it, with polygon index, vertex index and position.
+    Vertex(usize, usize, Position<f64>),
+    /// Object, with object index and position.
+    Object(usize, Position<f64>),
+    /// Picture, with picture index and position.
+    Picture(usize, Position<f64>)
 }
 
-impl Default for Version {
-    fn default() -> Version { Version::Elma }
+/// Single finding of a topology report.
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    /// Severity.
+    pub severity: Severity,
+    /// Problem found.
+    pub error: TopologyError,
+    /// Locations of the problem.
+    pub locations: Vec<Location>
+}
+
+impl Finding {
+    fn error (error: TopologyError, locations: Vec<Location>) -> Self {
+        Finding { severity: Severity::Error, error, locations }
+    }
+
+    fn warning (error: TopologyError, locations: Vec<Location>) -> Self {
+        Finding { severity: Severity::Warning, error, locations }
+    }
+}
+
+/// This trait specifies something having a rectangle bounding box.
+pub trait BoundingBox {
+    /// Bounding box of `&self`, going from top-left, top-right, bottom-left to bottom-right.
+    /// Returns `None` if there is nothing to bound.
+    fn bounding_box(&self) -> Option<[Position<f64>;4]>;
 }
 
 /// Type of object.
@@ -73,9 +171,10 @@ impl Default for ObjectType {
 }
 
 /// Apple direction object.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum Direction {
     /// No gravity change.
+    #[default]
     Normal,
     /// Gravity up.
     Up,
@@ -87,10 +186,6 @@ pub enum Direction {
     Right
 }
 
-impl Default for Direction {
-    fn default() -> Direction { Direction::Normal }
-}
-
 /// Object struct. Every level requires one `ObjectType::Player` Object and at least one `ObjectType::Exit` Object.
 #[derive(Debug, Default, PartialEq)]
 pub struct Object {
@@ -120,23 +215,8 @@ pub struct Polygon {
 }
 
 impl BoundingBox for Polygon {
-    fn bounding_box(&self) -> [Position<f64>; 4] {
-        let mut max_x = 0_f64;
-        let mut max_y = 0_f64;
-        let mut min_x = 0_f64;
-        let mut min_y = 0_f64;
-
-        for vertex in &self.vertices {
-            if vertex.x > max_x { max_x = vertex.x }
-            if vertex.x < min_x { min_x = vertex.x }
-            if vertex.y > max_y { max_y = vertex.y }
-            if vertex.y < min_y { min_y = vertex.y }
-        }
-
-        [Position { x: min_x, y: max_y },
-         Position { x: max_x, y: max_y },
-         Position { x: min_x, y: min_y },
-         Position { x: max_x, y: min_y }]
+    fn bounding_box(&self) -> Option<[Position<f64>; 4]> {
+        bounding_box_of(self.vertices.iter().map(|vertex| (vertex, 0_f64)))
     }
 }
 
@@ -151,20 +231,17 @@ impl Polygon {
 }
 
 /// Picture clipping.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub enum Clip {
     /// No clipping.
     Unclipped,
     /// Ground clipping.
     Ground,
     /// Sky clipping.
+    #[default]
     Sky
 }
 
-impl Default for Clip {
-    fn default() -> Clip { Clip::Sky }
-}
-
 /// Picture struct.
 #[derive(Debug, Default, PartialEq)]
 pub struct Picture {
@@ -203,8 +280,8 @@ pub struct ListEntry {
     pub name_1: String,
     /// Player 2 name.
     pub name_2: String,
-    /// Time.
-    pub time: i32
+    /// Time, stored in hundredths.
+    pub time: Time
 }
 
 impl Ord for ListEntry {
@@ -225,7 +302,7 @@ impl ListEntry {
         ListEntry {
             name_1: String::from("Player1"),
             name_2: String::from("Player2"),
-            time: 100000
+            time: Time::from_hundredths(100000)
         }
     }
 }
@@ -266,26 +343,12 @@ impl Default for Level {
 }
 
 impl BoundingBox for Level {
-    fn bounding_box(&self) -> [Position<f64>; 4] {
-        let mut max_x = 0_f64;
-        let mut max_y = 0_f64;
-        let mut min_x = 0_f64;
-        let mut min_y = 0_f64;
-
-        for polygon in &self.polygons {
-            let polygon_box = polygon.bounding_box();
-            for vertex in &polygon_box {
-                if vertex.x > max_x { max_x = vertex.x }
-                if vertex.x < min_x { min_x = vertex.x }
-                if vertex.y > max_y { max_y = vertex.y }
-                if vertex.y < min_y { min_y = vertex.y }
-            }
-        }
-
-        [Position { x: min_x, y: max_y },
-         Position { x: max_x, y: max_y },
-         Position { x: min_x, y: min_y },
-         Position { x: max_x, y: min_y }]
+    /// Bounding box of all polygons, objects and picture positions.
+    fn bounding_box(&self) -> Option<[Position<f64>; 4]> {
+        let vertices = self.polygons.iter().flat_map(|polygon| polygon.vertices.iter()).map(|vertex| (vertex, 0_f64));
+        let objects = self.objects.iter().map(|object| (&object.position, OBJECT_RADIUS));
+        let pictures = self.pictures.iter().map(|picture| (&picture.position, 0_f64));
+        bounding_box_of(vertices.chain(objects).chain(pictures))
     }
 }
 
@@ -298,10 +361,22 @@ impl Level {
     /// let level = elma::lev::Level::new();
     /// ```
     pub fn new () -> Self {
+        Level::new_with_rng(&mut ThreadRandom)
+    }
+
+    /// Returns a new `Level` struct, with the link number drawn from `rng`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let level = elma::lev::Level::new_with_rng(&mut elma::ZeroRng);
+    /// assert_eq!(level.link, 0);
+    /// ```
+    pub fn new_with_rng<R: RandomSource> (rng: &mut R) -> Self {
         Level {
             raw: vec![],
             version: Version::Elma,
-            link: random::<u32>(),
+            link: rng.next_u32(),
             integrity: [0f64; 4],
             name: String::new(),
             lgr: String::from("default"),
@@ -330,29 +405,65 @@ impl Level {
     /// let level = elma::lev::Level::load("tests/assets/levels/test_1.lev").unwrap();
     /// ```
     pub fn load<P: AsRef<Path>> (filename: P) -> Result<Self, ElmaError> {
-        let mut level = Level::new();
-        let mut file = File::open(filename)?;
+        Level::from_reader(File::open(filename)?)
+    }
+
+    /// Reads a level from any reader, like an upload body or a file inside an archive, and
+    /// returns a `Level` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let file = std::fs::File::open("tests/assets/levels/test_1.lev").unwrap();
+    /// let level = elma::lev::Level::from_reader(file).unwrap();
+    /// ```
+    pub fn from_reader<R: Read> (mut reader: R) -> Result<Self, ElmaError> {
         let mut buffer = vec![];
-        file.read_to_end(&mut buffer)?;
-        level.raw = buffer;
+        reader.read_to_end(&mut buffer)?;
+        Level::from_bytes(buffer)
+    }
+
+    /// Parses level data already in memory and returns a `Level` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut level = elma::lev::Level::new();
+    /// let bytes = level.get_raw(false).unwrap();
+    /// let parsed = elma::lev::Level::from_bytes(bytes).unwrap();
+    /// assert_eq!(parsed.link, level.link);
+    /// ```
+    pub fn from_bytes<B: Into<Vec<u8>>> (data: B) -> Result<Self, ElmaError> {
+        // Every random field is parsed, so there is no need to draw from the thread RNG.
+        let mut level = Level::new_with_rng(&mut ZeroRng);
+        level.raw = data.into();
         level.parse_level()?;
         Ok(level)
     }
 
     /// Parses the raw binary data into `Level` struct fields.
     fn parse_level (&mut self) -> Result<(), ElmaError> {
+        let mut context = ParseContext::new();
+        self.parse_level_data(&mut context).map_err(|error| context.wrap(error))
+    }
+
+    fn parse_level_data (&mut self, context: &mut ParseContext) -> Result<(), ElmaError> {
+        let total = self.raw.len();
         let remaining = self.raw.as_slice();
 
         // Version.
-        let (version, remaining) = remaining.split_at(5);
+        let (version, remaining) = checked_split_at(remaining, 5)?;
         self.version = match version {
             b"POT14" => Version::Elma,
-            b"POT06" => return Err(ElmaError::AcrossUnsupported),
+            b"POT06" => Version::Across,
             _ => return Err(ElmaError::InvalidLevelFile)
         };
 
-        // Link.
-        let (_, mut remaining) = remaining.split_at(2); // Never used
+        // Link. Elma levels also store the lower short of the link first, which is never used.
+        let mut remaining = match self.version {
+            Version::Elma => checked_split_at(remaining, 2)?.1,
+            Version::Across => remaining
+        };
         self.link = remaining.read_u32::<LittleEndian>()?;
 
         // Integrity checksums.
@@ -360,124 +471,179 @@ impl Level {
             self.integrity[i] = remaining.read_f64::<LittleEndian>()?;
         }
 
-        // Level name.
-        let (name, remaining) = remaining.split_at(51);
-        self.name = trim_string(name)?;
-        // LGR name.
-        let (lgr, remaining) = remaining.split_at(16);
-        self.lgr = trim_string(lgr)?;
-        // Ground texture name.
-        let (ground, remaining) = remaining.split_at(10);
-        self.ground = trim_string(ground)?;
-        // Sky texture name.
-        let (sky, mut remaining) = remaining.split_at(10);
-        self.sky = trim_string(sky)?;
+        context.section(total - remaining.len(), "level names");
+        let mut remaining = match self.version {
+            Version::Elma => {
+                // Level name.
+                let (name, remaining) = checked_split_at(remaining, 51)?;
+                self.name = trim_string(name)?;
+                // LGR name.
+                let (lgr, remaining) = checked_split_at(remaining, 16)?;
+                self.lgr = trim_string(lgr)?;
+                // Ground texture name.
+                let (ground, remaining) = checked_split_at(remaining, 10)?;
+                self.ground = trim_string(ground)?;
+                // Sky texture name.
+                let (sky, remaining) = checked_split_at(remaining, 10)?;
+                self.sky = trim_string(sky)?;
+                remaining
+            },
+            Version::Across => {
+                // Level name. Across levels have no LGR, ground or sky names.
+                let (name, remaining) = checked_split_at(remaining, 59)?;
+                self.name = trim_string(name)?;
+                remaining
+            }
+        };
 
         // Polygons.
+        context.section(total - remaining.len(), "polygon count");
         let poly_count = (remaining.read_f64::<LittleEndian>()? - 0.4643643).round() as usize;
-        let (polygons, read_bytes) = self.parse_polygons(remaining, poly_count)?;
+        context.section(total - remaining.len(), "polygons");
+        let (polygons, read_bytes) = self.parse_polygons(remaining, poly_count, context)?;
         self.polygons = polygons;
-        let (_, mut remaining) = remaining.split_at(read_bytes);
+        let (_, mut remaining) = checked_split_at(remaining, read_bytes)?;
 
         // Objects.
+        context.section(total - remaining.len(), "object count");
         let object_count = (remaining.read_f64::<LittleEndian>()? - 0.4643643).round() as usize;
-        let (object_data, mut remaining) = remaining.split_at(object_count*28);
-        self.objects = self.parse_objects(object_data, object_count)?;
+        context.section(total - remaining.len(), "objects");
+        let (object_data, mut remaining) = split_items(remaining, object_count, self.object_size())?;
+        self.objects = self.parse_objects(object_data, object_count, context)?;
+
+        // Across levels end after the objects, without pictures or top10 lists.
+        if self.version == Version::Across { return Ok(()) }
 
         // Pictures.
+        context.section(total - remaining.len(), "picture count");
         let picture_count = (remaining.read_f64::<LittleEndian>()? - 0.2345672).round() as usize;
-        let (picture_data, mut remaining) = remaining.split_at(picture_count*54);
-        self.pictures = self.parse_pictures(picture_data, picture_count)?;
+        context.section(total - remaining.len(), "pictures");
+        let (picture_data, mut remaining) = split_items(remaining, picture_count, 54)?;
+        self.pictures = self.parse_pictures(picture_data, picture_count, context)?;
 
         // EOD marker expected at this point.
+        context.section(total - remaining.len(), "end-of-data marker");
         let expected = remaining.read_i32::<LittleEndian>()?;
         if expected != EOD { return Err(ElmaError::EODMismatch) }
 
         // First decrypt the top10 blocks.
-        let (top10, mut remaining) = remaining.split_at(688);
+        context.section(total - remaining.len(), "top10 lists");
+        let (top10, mut remaining) = checked_split_at(remaining, 688)?;
         let decrypted_top10_data = crypt_top10(top10);
 
         // Single-player list.
+        let top10_offset = context.offset;
+        context.section(top10_offset, "single-player top10 list");
         let single = &decrypted_top10_data[0..344];
         self.top10_single = parse_top10(single)?;
 
         // Multi-player list.
+        context.section(top10_offset + 344, "multi-player top10 list");
         let multi = &decrypted_top10_data[344..688];
         self.top10_multi = parse_top10(multi)?;
 
         // EOF marker expected at this point.
+        context.section(total - remaining.len(), "end-of-file marker");
         let expected = remaining.read_i32::<LittleEndian>()?;
         if expected != EOF { return Err(ElmaError::EOFMismatch) }
 
         Ok(())
     }
 
-    fn parse_polygons (&self, mut buffer: &[u8], n: usize) -> Result<(Vec<Polygon>, usize), ElmaError> {
+    fn parse_polygons (&self, mut buffer: &[u8], n: usize, context: &mut ParseContext) -> Result<(Vec<Polygon>, usize), ElmaError> {
+        let start = context.offset;
+        // Every polygon takes at least its header, so larger counts can't be valid.
+        let header_size = match self.version { Version::Elma => 8, Version::Across => 4 };
+        if n > buffer.len() / header_size { return Err(ElmaError::UnexpectedEnd) }
         let mut polygons = vec![];
         let mut read_bytes = 0;
-        for _ in 0..n {
-            read_bytes += 8;
-            let grass = buffer.read_i32::<LittleEndian>()? > 0;
+        for index in 0..n {
+            context.item(start + read_bytes, "polygon", index);
+            // Across polygons have no grass flag.
+            let grass = match self.version {
+                Version::Elma => {
+                    read_bytes += 4;
+                    buffer.read_i32::<LittleEndian>()? > 0
+                },
+                Version::Across => false
+            };
+            read_bytes += 4;
             let vertex_count = buffer.read_i32::<LittleEndian>()?;
+            if vertex_count > 0 && vertex_count as usize > buffer.len() / 16 { return Err(ElmaError::UnexpectedEnd) }
             let mut vertices: Vec<Position<f64>> = vec![];
             for _ in 0..vertex_count {
                 read_bytes += 16;
                 let x = buffer.read_f64::<LittleEndian>()?;
                 let y = buffer.read_f64::<LittleEndian>()?;
                 vertices.push(Position {
-                    x: x,
-                    y: y
+                    x,
+                    y
                 });
             }
             polygons.push(Polygon {
-                grass: grass,
-                vertices: vertices
+                grass,
+                vertices
             });
         }
         Ok((polygons, read_bytes))
     }
 
-    fn parse_objects (&self, mut buffer: &[u8], n: usize) -> Result<Vec<Object>, ElmaError> {
+    /// Size of one object in bytes. Across objects have no gravity or animation.
+    fn object_size (&self) -> usize {
+        match self.version { Version::Elma => 28, Version::Across => 20 }
+    }
+
+    fn parse_objects (&self, mut buffer: &[u8], n: usize, context: &mut ParseContext) -> Result<Vec<Object>, ElmaError> {
+        let start = context.offset;
         let mut objects = vec![];
-        for _ in 0..n {
+        for index in 0..n {
+            context.item(start + index * self.object_size(), "object", index);
             let x = buffer.read_f64::<LittleEndian>()?;
             let y = buffer.read_f64::<LittleEndian>()?;
-            let position = Position { x: x, y: y };
+            let position = Position { x, y };
             let object_type = buffer.read_i32::<LittleEndian>()?;
-            let gravity = buffer.read_i32::<LittleEndian>()?;
-            let gravity_direction = match gravity {
-                0 => Direction::Normal,
-                1 => Direction::Up,
-                2 => Direction::Down,
-                3 => Direction::Left,
-                4 => Direction::Right,
-                other => return Err(ElmaError::InvalidGravity(other))
+            // Across apples have no gravity or animation.
+            let (gravity_direction, animation) = match self.version {
+                Version::Elma => {
+                    let gravity = buffer.read_i32::<LittleEndian>()?;
+                    let gravity_direction = match gravity {
+                        0 => Direction::Normal,
+                        1 => Direction::Up,
+                        2 => Direction::Down,
+                        3 => Direction::Left,
+                        4 => Direction::Right,
+                        other => return Err(ElmaError::InvalidGravity(other))
+                    };
+                    (gravity_direction, buffer.read_i32::<LittleEndian>()? + 1)
+                },
+                Version::Across => (Direction::Normal, 1)
             };
-            let animation = buffer.read_i32::<LittleEndian>()? + 1;
             let object = match object_type {
                 1 => ObjectType::Exit,
-                2 => ObjectType::Apple { gravity: gravity_direction, animation: animation },
+                2 => ObjectType::Apple { gravity: gravity_direction, animation },
                 3 => ObjectType::Killer,
                 4 => ObjectType::Player,
                 other => return Err(ElmaError::InvalidObject(other))
             };
 
             objects.push(Object {
-                position: position,
+                position,
                 object_type: object
             });
         }
         Ok(objects)
     }
 
-    fn parse_pictures (&self, mut buffer: &[u8], n: usize) -> Result<Vec<Picture>, ElmaError> {
+    fn parse_pictures (&self, mut buffer: &[u8], n: usize, context: &mut ParseContext) -> Result<Vec<Picture>, ElmaError> {
+        let start = context.offset;
         let mut pictures = vec![];
-        for _ in 0..n {
-            let (name, temp_remaining) = buffer.split_at(10);
+        for index in 0..n {
+            context.item(start + index * 54, "picture", index);
+            let (name, temp_remaining) = checked_split_at(buffer, 10)?;
             let name = trim_string(name)?;
-            let (texture, temp_remaining) = temp_remaining.split_at(10);
+            let (texture, temp_remaining) = checked_split_at(temp_remaining, 10)?;
             let texture = trim_string(texture)?;
-            let (mask, temp_remaining) = temp_remaining.split_at(10);
+            let (mask, temp_remaining) = checked_split_at(temp_remaining, 10)?;
             let mask = trim_string(mask)?;
             buffer = temp_remaining;
             let x = buffer.read_f64::<LittleEndian>()?;
@@ -492,12 +658,12 @@ impl Level {
             };
 
             pictures.push(Picture {
-                name: name,
-                texture: texture,
-                mask: mask,
-                position: Position { x: x, y: y },
-                distance: distance,
-                clip: clip
+                name,
+                texture,
+                mask,
+                position: Position { x, y },
+                distance,
+                clip
             });
         }
         Ok(pictures)
@@ -507,6 +673,10 @@ impl Level {
     /// integrity sums. Called automatically when using the `get_raw` or `save` methods,
     /// and is provided mainly for convinience if you need to use it manually.
     ///
+    /// Across levels can not hold grass polygons, pictures, apple gravity or top10 lists, so
+    /// these are left out when `version` is `Version::Across`. The last integrity sum of Across
+    /// levels is copied from a single real Across level, so they may not load in the game.
+    ///
     /// # Arguments
     ///
     /// * top_10 - Whether to update top10 lists or not.
@@ -519,45 +689,47 @@ impl Level {
     /// level.update(false);
     /// ```
     pub fn update (&mut self, top_10: bool) -> Result<(), ElmaError> {
-        let mut bytes = vec![];
+        self.update_with_rng(top_10, &mut ThreadRandom)
+    }
 
+    /// Like `update`, but draws the random parts of the integrity sums from `rng`. Use a seeded
+    /// generator or `ZeroRng` to get the same bytes every time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut level = elma::lev::Level::new_with_rng(&mut elma::ZeroRng);
+    /// level.update_with_rng(false, &mut elma::ZeroRng).unwrap();
+    /// let first = level.raw.clone();
+    /// level.update_with_rng(false, &mut elma::ZeroRng).unwrap();
+    /// assert_eq!(level.raw, first);
+    /// ```
+    pub fn update_with_rng<R: RandomSource> (&mut self, top_10: bool, rng: &mut R) -> Result<(), ElmaError> {
         // Level version.
-        match self.version {
-            Version::Elma => bytes.extend_from_slice(&[80, 79, 84, 49, 52]),
-            Version::Across => return Err(ElmaError::AcrossUnsupported)
-        };
+        let mut bytes = self.write_version(vec![])?;
 
-        // Lower short of link.
-        bytes.write_i16::<LittleEndian>((self.link & 0xFFFF) as i16)?;
         // Link.
         bytes.write_u32::<LittleEndian>(self.link)?;
         // Integrity checksums.
-        self.calculate_integrity_sums(true);
-        for sum in self.integrity.into_iter() {
-            bytes.write_f64::<LittleEndian>(*sum)?;
-        }
+        self.calculate_integrity_sums(true, rng);
+        bytes = self.write_integrity(bytes)?;
 
-        // Level name.
-        bytes.extend_from_slice(&string_null_pad(&self.name, 51)?);
-        // LGR name.
-        bytes.extend_from_slice(&string_null_pad(&self.lgr, 16)?);
-        // Ground name.
-        bytes.extend_from_slice(&string_null_pad(&self.ground, 10)?);
-        // Sky name.
-        bytes.extend_from_slice(&string_null_pad(&self.sky, 10)?);
+        // Level, LGR, ground and sky names.
+        for (name, size) in self.names() {
+            bytes.extend_from_slice(&string_null_pad(name, size)?);
+        }
 
-        // Number of polygons.
-        bytes.write_f64::<LittleEndian>(self.polygons.len() as f64 + 0.4643643_f64)?;
         // Polygons.
         bytes = self.write_polygons(bytes)?;
-
-        // Number of objects.
-        bytes.write_f64::<LittleEndian>(self.objects.len() as f64 + 0.4643643_f64)?;
         // Objects.
         bytes = self.write_objects(bytes)?;
 
-        // Number of pictures.
-        bytes.write_f64::<LittleEndian>(self.pictures.len() as f64 + 0.2345672_f64)?;
+        // Across levels end after the objects.
+        if self.version == Version::Across {
+            self.raw = bytes;
+            return Ok(());
+        }
+
         // Pictures.
         bytes = self.write_pictures(bytes)?;
 
@@ -566,10 +738,7 @@ impl Level {
 
         // Top10 lists.
         if top_10 {
-            // Order lists first.
-            self.top10_single.sort();
-            self.top10_multi.sort();
-            bytes = self.write_top10(bytes)?;
+            bytes.extend_from_slice(&self.encrypted_top10()?);
         } else {
             bytes.extend_from_slice(&EMPTY_TOP10);
         }
@@ -581,10 +750,201 @@ impl Level {
         Ok(())
     }
 
+    /// Like `update` with top10 lists, but only re-encodes the parts of `raw` that were
+    /// changed since it was loaded. An unmodified level gives back the exact same bytes, including
+    /// unused link bytes, garbage after string terminators and encrypted top10 data. Integrity
+    /// sums are only recalculated if polygons, objects or pictures changed.
+    ///
+    /// Falls back to `update(true)` if `raw` is not a valid level of the same version.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut level = elma::lev::Level::load("tests/assets/levels/test_1.lev").unwrap();
+    /// let original = level.raw.clone();
+    /// level.update_lossless().unwrap();
+    /// assert_eq!(level.raw, original);
+    /// ```
+    pub fn update_lossless (&mut self) -> Result<(), ElmaError> {
+        self.update_lossless_with_rng(&mut ThreadRandom)
+    }
+
+    /// Like `update_lossless`, but draws the random parts of recalculated integrity sums from
+    /// `rng`.
+    pub fn update_lossless_with_rng<R: RandomSource> (&mut self, rng: &mut R) -> Result<(), ElmaError> {
+        let original = match Level::from_bytes(&self.raw[..]) {
+            Ok(original) => original,
+            Err(_) => return self.update_with_rng(true, rng)
+        };
+        if original.version != self.version { return self.update_with_rng(true, rng) }
+        let raw = &original.raw;
+        let mut bytes = vec![];
+
+        // Level version, and unused lower short of link.
+        let header_size = match self.version { Version::Elma => 7, Version::Across => 5 };
+        if self.link == original.link {
+            bytes.extend_from_slice(&raw[0..header_size]);
+        } else {
+            bytes = self.write_version(bytes)?;
+        }
+        let mut offset = header_size;
+
+        // Link.
+        bytes.write_u32::<LittleEndian>(self.link)?;
+        offset += 4;
+
+        // Integrity checksums, only recalculated if the level changed.
+        if self.polygons != original.polygons || self.objects != original.objects || self.pictures != original.pictures {
+            self.calculate_integrity_sums(true, rng);
+        }
+        if self.integrity == original.integrity {
+            bytes.extend_from_slice(&raw[offset..offset + 32]);
+        } else {
+            bytes = self.write_integrity(bytes)?;
+        }
+        offset += 32;
+
+        // Names.
+        for ((name, size), (original_name, _)) in self.names().into_iter().zip(original.names()) {
+            if name == original_name {
+                bytes.extend_from_slice(&raw[offset..offset + size]);
+            } else {
+                bytes.extend_from_slice(&string_null_pad(name, size)?);
+            }
+            offset += size;
+        }
+
+        // Polygons.
+        let polygon_header = match self.version { Version::Elma => 8, Version::Across => 4 };
+        let size = 8 + original.polygons.iter().fold(0, |total, poly| total + polygon_header + poly.vertices.len() * 16);
+        if self.polygons == original.polygons {
+            bytes.extend_from_slice(&raw[offset..offset + size]);
+        } else {
+            bytes = self.write_polygons(bytes)?;
+        }
+        offset += size;
+
+        // Objects.
+        let size = 8 + original.objects.len() * self.object_size();
+        if self.objects == original.objects {
+            bytes.extend_from_slice(&raw[offset..offset + size]);
+        } else {
+            bytes = self.write_objects(bytes)?;
+        }
+        offset += size;
+
+        if self.version == Version::Elma {
+            // Pictures.
+            let size = 8 + original.pictures.len() * 54;
+            if self.pictures == original.pictures {
+                bytes.extend_from_slice(&raw[offset..offset + size]);
+            } else {
+                bytes = self.write_pictures(bytes)?;
+            }
+            offset += size;
+
+            // EOD marker.
+            bytes.extend_from_slice(&raw[offset..offset + 4]);
+            offset += 4;
+
+            // Top10 lists.
+            if self.top10_single == original.top10_single && self.top10_multi == original.top10_multi {
+                bytes.extend_from_slice(&raw[offset..offset + 688]);
+            } else {
+                bytes.extend_from_slice(&self.encrypted_top10()?);
+            }
+            offset += 688;
+        }
+
+        // EOF marker, and anything after the level data.
+        bytes.extend_from_slice(&raw[offset..]);
+
+        self.raw = bytes;
+        Ok(())
+    }
+
+    /// Saves level as a file, only re-encoding the parts that were changed since loading it.
+    /// See `update_lossless`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// let mut level = elma::lev::Level::load("tests/assets/levels/test_1.lev").unwrap();
+    /// level.name = String::from("Renamed");
+    /// level.save_lossless("renamed.lev").unwrap();
+    /// ```
+    pub fn save_lossless<P: AsRef<Path>> (&mut self, filename: P) -> Result<(), ElmaError> {
+        self.write_to_lossless(File::create(filename)?)
+    }
+
+    /// Writes the level to any writer, only re-encoding the parts that were changed since
+    /// loading it. See `update_lossless`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut level = elma::lev::Level::load("tests/assets/levels/test_1.lev").unwrap();
+    /// let original = level.raw.clone();
+    /// let mut buffer = vec![];
+    /// level.write_to_lossless(&mut buffer).unwrap();
+    /// assert_eq!(buffer, original);
+    /// ```
+    pub fn write_to_lossless<W: Write> (&mut self, mut writer: W) -> Result<(), ElmaError> {
+        self.update_lossless()?;
+        writer.write_all(&self.raw)?;
+        Ok(())
+    }
+
+    /// Whether a polygon is part of the written level. Across has no grass polygons.
+    fn writes_polygon (&self, poly: &Polygon) -> bool {
+        self.version == Version::Elma || !poly.grass
+    }
+
+    /// Name fields and their sizes, in file order.
+    fn names (&self) -> Vec<(&String, usize)> {
+        match self.version {
+            Version::Elma => vec![(&self.name, 51), (&self.lgr, 16), (&self.ground, 10), (&self.sky, 10)],
+            Version::Across => vec![(&self.name, 59)]
+        }
+    }
+
+    fn write_version (&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, ElmaError> {
+        match self.version {
+            Version::Elma => {
+                bytes.extend_from_slice(b"POT14");
+                // Lower short of link.
+                bytes.write_i16::<LittleEndian>((self.link & 0xFFFF) as i16)?;
+            },
+            Version::Across => bytes.extend_from_slice(b"POT06")
+        };
+        Ok(bytes)
+    }
+
+    fn write_integrity (&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, ElmaError> {
+        for sum in &self.integrity {
+            bytes.write_f64::<LittleEndian>(*sum)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Sorts the top10 lists and encrypts them.
+    fn encrypted_top10 (&mut self) -> Result<Vec<u8>, ElmaError> {
+        self.top10_single.sort();
+        self.top10_multi.sort();
+        let mut top10_bytes = write_top10(&self.top10_single)?;
+        top10_bytes.extend_from_slice(&write_top10(&self.top10_multi)?);
+        Ok(crypt_top10(&top10_bytes))
+    }
+
     fn write_polygons (&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, ElmaError> {
-        for poly in &self.polygons {
+        // Number of polygons.
+        let poly_count = self.polygons.iter().filter(|poly| self.writes_polygon(poly)).count();
+        bytes.write_f64::<LittleEndian>(poly_count as f64 + 0.4643643_f64)?;
+        for poly in self.polygons.iter().filter(|poly| self.writes_polygon(poly)) {
             // Grass poly.
-            bytes.write_i32::<LittleEndian>(if poly.grass { 1 } else { 0 })?;
+            if self.version == Version::Elma {
+                bytes.write_i32::<LittleEndian>(if poly.grass { 1 } else { 0 })?;
+            }
             // Number of vertices.
             bytes.write_i32::<LittleEndian>(poly.vertices.len() as i32)?;
             // Vertices.
@@ -597,6 +957,8 @@ impl Level {
     }
 
     fn write_objects (&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, ElmaError> {
+        // Number of objects.
+        bytes.write_f64::<LittleEndian>(self.objects.len() as f64 + 0.4643643_f64)?;
         for obj in &self.objects {
             // Position.
             bytes.write_f64::<LittleEndian>(obj.position.x)?;
@@ -608,6 +970,8 @@ impl Level {
                 ObjectType::Killer => 3,
                 ObjectType::Player => 4
             })?;
+            // Across objects have no gravity or animation.
+            if self.version == Version::Across { continue }
             // Apple gravity.
             bytes.write_i32::<LittleEndian>(match obj.object_type {
                 ObjectType::Apple { gravity: Direction::Up, .. } => 1,
@@ -618,7 +982,7 @@ impl Level {
             })?;
             // Apple animation.
             bytes.write_i32::<LittleEndian>(match obj.object_type {
-                ObjectType::Apple { animation: n, .. } => (n - 1) as i32,
+                ObjectType::Apple { animation: n, .. } => n - 1,
                 _ => 0
             })?;
         }
@@ -626,6 +990,8 @@ impl Level {
     }
 
     fn write_pictures (&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, ElmaError> {
+        // Number of pictures.
+        bytes.write_f64::<LittleEndian>(self.pictures.len() as f64 + 0.2345672_f64)?;
         for pic in &self.pictures {
             // Picture name.
             bytes.extend_from_slice(&string_null_pad(&pic.name, 10)?);
@@ -648,97 +1014,194 @@ impl Level {
         Ok(bytes)
     }
 
-    fn write_top10 (&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, ElmaError> {
-        let mut top10_bytes: Vec<u8> = vec![];
-
-        // Single-player times.
-        let single_times = self.top10_single.len();
-        top10_bytes.write_i32::<LittleEndian>(if 10 < single_times { 10 } else { single_times } as i32)?;
-        let mut times = [0_i32;10];
-        let mut names_1 = vec![];
-        let mut names_2 = vec![];
-        for (n, entry) in self.top10_single.iter().enumerate() {
-            if n < 10 {
-                times[n] = entry.time;
-                names_1.extend_from_slice(&string_null_pad(&entry.name_1, 15)?);
-                names_2.extend_from_slice(&string_null_pad(&entry.name_2, 15)?);
-            }
+    /// Width of level based on left- and right-most vertices.
+    pub fn width(&self) -> f64 {
+        match self.vertex_bounding_box() {
+            Some(level_box) => level_box[1].x - level_box[0].x,
+            None => 0_f64
         }
-        // Pad with null bytes if less than 10 entries.
-        if single_times < 10 {
-            for _ in 0..10 - single_times {
-                names_1.extend_from_slice(&[0u8;15]);
-                names_2.extend_from_slice(&[0u8;15]);
-            }
+    }
+
+    /// Height of level based on top and bottom-most vertices.
+    pub fn height(&self) -> f64 {
+        match self.vertex_bounding_box() {
+            Some(level_box) => level_box[0].y - level_box[2].y,
+            None => 0_f64
         }
+    }
+
+    /// Bounding box of polygon vertices only.
+    fn vertex_bounding_box(&self) -> Option<[Position<f64>; 4]> {
+        bounding_box_of(self.polygons.iter().flat_map(|polygon| polygon.vertices.iter()).map(|vertex| (vertex, 0_f64)))
+    }
 
-        for time in &times {
-            top10_bytes.write_i32::<LittleEndian>(*time)?;
+    /// Check topology of level.
+    pub fn check_topology (&self) -> Result<(), TopologyError>  {
+        first_error(self.topology_report(None))
+    }
+
+    /// Check topology of level, and check pictures against the LGR used by the level.
+    pub fn check_topology_with_lgr (&self, lgr: &Lgr) -> Result<(), TopologyError> {
+        first_error(self.topology_report(Some(lgr)))
+    }
+
+    /// Runs every topology check and returns all findings, in the same order as
+    /// `check_topology` checks them. Pictures are only checked if `lgr` is given.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut level = elma::lev::Level::new();
+    /// level.objects.clear();
+    /// let report = level.topology_report(None);
+    /// assert_eq!(report.len(), 2);
+    /// assert_eq!(report[0].error, elma::lev::TopologyError::InvalidPlayerCount(0));
+    /// assert_eq!(report[1].error, elma::lev::TopologyError::MissingExit);
+    /// ```
+    pub fn topology_report (&self, lgr: Option<&Lgr>) -> Vec<Finding> {
+        let mut findings = self.object_findings();
+        findings.extend(self.size_findings());
+        findings.extend(self.vertex_count_findings());
+        findings.extend(self.overlapping_polygons_findings());
+        findings.extend(self.head_findings());
+        findings.extend(self.apple_findings());
+        if let Some(lgr) = lgr {
+            findings.extend(self.picture_findings(lgr));
         }
+        findings
+    }
 
-        top10_bytes.extend_from_slice(&names_1);
-        top10_bytes.extend_from_slice(&names_2);
+    /// Check that every picture, texture and mask name exists in `lgr` with the right type,
+    /// that every texture has a mask, and that distances are within 1..999. Clipping is already
+    /// validated when parsing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut lgr = elma::lgr::Lgr::new();
+    /// lgr.pictures.push(elma::lgr::PictureData::new("barrel"));
+    /// let mut level = elma::lev::Level::new();
+    /// level.pictures.push(elma::lev::Picture::new());
+    /// assert!(level.check_pictures(&lgr).is_ok());
+    /// ```
+    pub fn check_pictures (&self, lgr: &Lgr) -> Result<(), TopologyError> {
+        first_error(self.picture_findings(lgr))
+    }
 
-        // Multi-player times.
-        let multi_times = self.top10_multi.len();
-        top10_bytes.write_i32::<LittleEndian>(if 10 < multi_times { 10 } else { multi_times } as i32)?;
-        let mut times = [0_i32;10];
-        let mut names_1 = vec![];
-        let mut names_2 = vec![];
-        for (n, entry) in self.top10_multi.iter().enumerate() {
-            if n < 10 {
-                times[n] = entry.time;
-                names_1.extend_from_slice(&string_null_pad(&entry.name_1, 15)?);
-                names_2.extend_from_slice(&string_null_pad(&entry.name_2, 15)?);
+    /// Returns whether a point is in ground, i.e. inside an even number of non-grass polygons.
+    /// Points outside all polygons are in ground.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let level = elma::lev::Level::new();
+    /// assert!(!level.is_ground(&elma::Position { x: 5_f64, y: 5_f64 }));
+    /// assert!(level.is_ground(&elma::Position { x: 15_f64, y: 5_f64 }));
+    /// ```
+    pub fn is_ground (&self, point: &Position<f64>) -> bool {
+        let mut inside = 0;
+        for polygon in self.polygons.iter().filter(|polygon| !polygon.grass) {
+            let mut crossings = false;
+            let count = polygon.vertices.len();
+            for (n, start) in polygon.vertices.iter().enumerate() {
+                let end = &polygon.vertices[(n + 1) % count];
+                if (start.y > point.y) != (end.y > point.y) &&
+                   point.x < start.x + (point.y - start.y) / (end.y - start.y) * (end.x - start.x) {
+                    crossings = !crossings;
+                }
             }
+            if crossings { inside += 1 }
         }
-        // Pad with null bytes if less than 10 entries.
-        if multi_times < 10 {
-            for _ in 0..10 - multi_times {
-                names_1.extend_from_slice(&[0u8;15]);
-                names_2.extend_from_slice(&[0u8;15]);
+        inside % 2 == 0
+    }
+
+    /// Shortest distance from a point to any non-grass polygon edge.
+    fn distance_to_ground (&self, point: &Position<f64>) -> f64 {
+        let mut distance = f64::MAX;
+        for polygon in self.polygons.iter().filter(|polygon| !polygon.grass) {
+            let count = polygon.vertices.len();
+            for (n, start) in polygon.vertices.iter().enumerate() {
+                let end = &polygon.vertices[(n + 1) % count];
+                distance = distance.min(distance_to_segment(point, start, end));
             }
         }
+        distance
+    }
 
-        for time in &times {
-            top10_bytes.write_i32::<LittleEndian>(*time)?;
+    fn head_findings(&self) -> Vec<Finding> {
+        let mut findings = vec![];
+        for (n, object) in self.objects.iter().enumerate() {
+            if object.object_type != ObjectType::Player { continue }
+            let head = Position { x: object.position.x + HEAD_OFFSET.x, y: object.position.y + HEAD_OFFSET.y };
+            if self.is_ground(&head) || self.distance_to_ground(&head) < HEAD_RADIUS {
+                findings.push(Finding::warning(TopologyError::HeadInsideGround,
+                                             vec![Location::Object(n, object.position)]));
+            }
         }
+        findings
+    }
 
-        top10_bytes.extend_from_slice(&names_1);
-        top10_bytes.extend_from_slice(&names_2);
+    fn apple_findings(&self) -> Vec<Finding> {
+        let mut error_apples = vec![];
+        for (n, object) in self.objects.iter().enumerate() {
+            if let ObjectType::Apple { .. } = object.object_type {
+                if self.is_ground(&object.position) && self.distance_to_ground(&object.position) >= OBJECT_RADIUS {
+                    error_apples.push(n);
+                }
+            }
+        }
 
-        // Encrypt the data before writing.
-        bytes.extend_from_slice(&crypt_top10(&top10_bytes));
-        Ok(bytes)
+        if error_apples.is_empty() { return vec![] }
+        let locations = error_apples.iter().map(|&n| Location::Object(n, self.objects[n].position)).collect();
+        vec![Finding::warning(TopologyError::AppleInsideGround(error_apples), locations)]
     }
 
-    /// Width of level based on left- and right-most vertices.
-    pub fn width(&self) -> f64 {
-        let level_box = &self.bounding_box();
-        (level_box[0].x + level_box[1].x).abs()
-    }
+    fn picture_findings(&self, lgr: &Lgr) -> Vec<Finding> {
+        let exists = |name: &str, picture_type: PictureType| {
+            lgr.picture(name).is_some_and(|picture| picture.picture_type == picture_type)
+        };
 
-    /// Height of level based on top and bottom-most vertices.
-    pub fn height(&self) -> f64 {
-        let level_box = &self.bounding_box();
-        (level_box[2].y + level_box[0].y).abs()
-    }
+        let mut unknown = vec![];
+        let mut missing_mask = vec![];
+        let mut invalid_distance = vec![];
+        for (n, picture) in self.pictures.iter().enumerate() {
+            if picture.name.is_empty() {
+                if picture.texture.is_empty() || !exists(&picture.texture, PictureType::Texture) ||
+                   (!picture.mask.is_empty() && !exists(&picture.mask, PictureType::Mask)) {
+                    unknown.push(n);
+                }
+                if !picture.texture.is_empty() && picture.mask.is_empty() {
+                    missing_mask.push(n);
+                }
+            } else if !exists(&picture.name, PictureType::Normal) {
+                unknown.push(n);
+            }
 
-    /// Check topology of level.
-    pub fn check_topology (&self) -> Result<(), TopologyError>  {
-        &self.check_objects()?;
-        if *&self.width() > 188_f64 { return Err(TopologyError::TooWide(*&self.width() - 188_f64)) }
-        if *&self.height() > 188_f64 { return Err(TopologyError::TooHigh(*&self.height() - 188_f64)) }
-        &self.check_vertex_count()?;
-        &self.check_overlapping_polygons()?;
-        // TODO: check line segment overlaps
-        // TODO: check if head inside ground
-        // TODO: check if apples fully inside ground
-        Ok(())
+            if picture.distance < 1 || picture.distance > 999 {
+                invalid_distance.push(n);
+            }
+        }
+
+        let locations = |pictures: &[usize]| {
+            pictures.iter().map(|&n| Location::Picture(n, self.pictures[n].position)).collect()
+        };
+        let mut findings = vec![];
+        if !unknown.is_empty() {
+            let locations = locations(&unknown);
+            findings.push(Finding::error(TopologyError::UnknownPictures(unknown), locations));
+        }
+        if !missing_mask.is_empty() {
+            let locations = locations(&missing_mask);
+            findings.push(Finding::error(TopologyError::MissingMask(missing_mask), locations));
+        }
+        if !invalid_distance.is_empty() {
+            let locations = locations(&invalid_distance);
+            findings.push(Finding::error(TopologyError::InvalidPictureDistance(invalid_distance), locations));
+        }
+        findings
     }
 
-    /// Returns a vector with the indexes of polygons containing too few vertices.
-    fn check_vertex_count(&self) -> Result<(), TopologyError> {
+    fn vertex_count_findings(&self) -> Vec<Finding> {
         let mut error_polygons = vec![];
         for (n, polygon) in self.polygons.iter().enumerate() {
             if polygon.vertices.len() < 3 {
@@ -746,55 +1209,161 @@ impl Level {
             }
         }
 
-        if !error_polygons.is_empty() {
-            return Err(TopologyError::InvalidVertexCount(error_polygons));
-        }
-
-        Ok(())
+        if error_polygons.is_empty() { return vec![] }
+        let locations = error_polygons.iter().map(|&n| Location::Polygon(n)).collect();
+        vec![Finding::error(TopologyError::InvalidVertexCount(error_polygons), locations)]
     }
 
-    fn check_objects(&self) -> Result<(), TopologyError> {
-        if *&self.polygons.len() > 1000 {
-            return Err(TopologyError::MaxPolygons(&self.polygons.len() - 1000))
+    fn object_findings(&self) -> Vec<Finding> {
+        let mut findings = vec![];
+        if self.polygons.len() > 1000 {
+            findings.push(Finding::error(TopologyError::MaxPolygons(self.polygons.len() - 1000), vec![Location::Level]));
         }
 
-        if *&self.objects.len() > 252 {
-            return Err(TopologyError::MaxObjects(&self.objects.len() - 252))
+        if self.objects.len() > 252 {
+            findings.push(Finding::error(TopologyError::MaxObjects(self.objects.len() - 252), vec![Location::Level]));
         }
 
-        if *&self.pictures.len() > 5000 {
-            return Err(TopologyError::MaxPictures(&self.pictures.len() - 5000))
+        if self.pictures.len() > 5000 {
+            findings.push(Finding::error(TopologyError::MaxPictures(self.pictures.len() - 5000), vec![Location::Level]));
         }
 
-        let player_count = *&self.objects.iter().fold(0, |total, object| if object.object_type == ObjectType::Player { total + 1} else { total });
-        if player_count != 1 {
-            return Err(TopologyError::InvalidPlayerCount(player_count))
+        let players: Vec<_> = self.objects.iter().enumerate()
+                                  .filter(|&(_, object)| object.object_type == ObjectType::Player)
+                                  .map(|(n, object)| Location::Object(n, object.position))
+                                  .collect();
+        if players.len() != 1 {
+            findings.push(Finding::error(TopologyError::InvalidPlayerCount(players.len()), players));
         }
 
-        let exit_count = *&self.objects.iter().fold(0, |total, object| if object.object_type == ObjectType::Exit { total + 1} else { total });
+        let exit_count = self.objects.iter().fold(0, |total, object| if object.object_type == ObjectType::Exit { total + 1} else { total });
         if exit_count < 1 {
-            return Err(TopologyError::MissingExit)
+            findings.push(Finding::error(TopologyError::MissingExit, vec![Location::Level]));
         }
 
-        Ok(())
+        findings
     }
 
-    fn check_overlapping_polygons(&self) -> Result<(), TopologyError> {
-        for poly_base in &self.polygons {
-            if poly_base.grass { break; } // ignore anything involving grass polygons
-            // first check overlapping lines within base polygon
+    fn size_findings(&self) -> Vec<Finding> {
+        let mut findings = vec![];
+        if self.width() > 188_f64 {
+            findings.push(Finding::error(TopologyError::TooWide(self.width() - 188_f64), vec![Location::Level]));
+        }
+        if self.height() > 188_f64 {
+            findings.push(Finding::error(TopologyError::TooHigh(self.height() - 188_f64), vec![Location::Level]));
+        }
+        findings
+    }
 
+    fn overlapping_polygons_findings(&self) -> Vec<Finding> {
+        // Edges of all non-grass polygons, as (polygon index, edge index, start, end).
+        let mut edges = vec![];
+        for (n, polygon) in self.polygons.iter().enumerate() {
+            if polygon.grass { continue } // ignore anything involving grass polygons
+            let count = polygon.vertices.len();
+            for (i, start) in polygon.vertices.iter().enumerate() {
+                edges.push((n, i, start, &polygon.vertices[(i + 1) % count]));
+            }
         }
-        Ok(())
+        if edges.is_empty() { return vec![] }
+
+        // Bounding boxes of the edges.
+        let bounds: Vec<_> = edges.iter().map(|&(_, _, start, end)| (
+            Position { x: start.x.min(end.x), y: start.y.min(end.y) },
+            Position { x: start.x.max(end.x), y: start.y.max(end.y) }
+        )).collect();
+        let mut min = Position { x: f64::MAX, y: f64::MAX };
+        let mut max = Position { x: f64::MIN, y: f64::MIN };
+        for &(low, high) in &bounds {
+            min.x = min.x.min(low.x);
+            min.y = min.y.min(low.y);
+            max.x = max.x.max(high.x);
+            max.y = max.y.max(high.y);
+        }
+
+        let mut error_edges = BTreeSet::new();
+        let mut test = |a: usize, b: usize| {
+            let (poly_a, edge_a, start_a, end_a) = edges[a];
+            let (poly_b, edge_b, start_b, end_b) = edges[b];
+            if poly_a == poly_b {
+                // Neighbouring edges always share a vertex.
+                let count = self.polygons[poly_a].vertices.len();
+                if (edge_a + 1) % count == edge_b || (edge_b + 1) % count == edge_a { return }
+            }
+            if do_line_segment_intersect(start_a, end_a, start_b, end_b) {
+                error_edges.insert((poly_a, edge_a));
+                error_edges.insert((poly_b, edge_b));
+            }
+        };
+
+        // Sort edges into a uniform grid, so only edges sharing a cell need to be compared.
+        let cells = ((edges.len() as f64).sqrt().ceil() as usize).clamp(1, 256);
+        let cell_width = if max.x > min.x { (max.x - min.x) / cells as f64 } else { 1_f64 };
+        let cell_height = if max.y > min.y { (max.y - min.y) / cells as f64 } else { 1_f64 };
+        let cell = |value: f64, min: f64, size: f64| (((value - min) / size) as usize).min(cells - 1);
+        let cell_ranges: Vec<_> = bounds.iter().map(|&(low, high)| (
+            cell(low.x, min.x, cell_width)..=cell(high.x, min.x, cell_width),
+            cell(low.y, min.y, cell_height)..=cell(high.y, min.y, cell_height)
+        )).collect();
+        let entries: usize = cell_ranges.iter().map(|(x, y)| (x.end() - x.start() + 1) * (y.end() - y.start() + 1)).sum();
+
+        if entries <= edges.len() * MAX_CELLS_PER_EDGE {
+            let mut grid = vec![vec![]; cells * cells];
+            for (id, (x_range, y_range)) in cell_ranges.into_iter().enumerate() {
+                for y in y_range {
+                    for x in x_range.clone() {
+                        grid[x + y * cells].push(id);
+                    }
+                }
+            }
+
+            let mut tested = HashSet::new();
+            for ids in &grid {
+                for (k, &a) in ids.iter().enumerate() {
+                    for &b in &ids[k + 1..] {
+                        if tested.insert((a, b)) { test(a, b) }
+                    }
+                }
+            }
+        } else {
+            // Sweep along the axis the edges are shorter on, relative to the level size, and
+            // compare edges whose bounding boxes overlap.
+            let (span_x, span_y) = bounds.iter().fold((0_f64, 0_f64), |(x, y), &(low, high)| {
+                (x + (high.x - low.x) / cell_width, y + (high.y - low.y) / cell_height)
+            });
+            let axis = |position: Position<f64>| if span_x <= span_y { (position.x, position.y) } else { (position.y, position.x) };
+            let bounds: Vec<_> = bounds.into_iter().map(|(low, high)| (axis(low), axis(high))).collect();
+            let mut order: Vec<_> = (0..edges.len()).collect();
+            order.sort_by(|&a, &b| bounds[a].0 .0.total_cmp(&bounds[b].0 .0));
+
+            let mut active: Vec<usize> = vec![];
+            for a in order {
+                let (low_a, high_a) = bounds[a];
+                active.retain(|&b| bounds[b].1 .0 >= low_a.0);
+                for &b in &active {
+                    let (low_b, high_b) = bounds[b];
+                    if low_b.1 <= high_a.1 && low_a.1 <= high_b.1 { test(a, b) }
+                }
+                active.push(a);
+            }
+        }
+
+        if error_edges.is_empty() { return vec![] }
+        let mut error_polygons: Vec<_> = error_edges.iter().map(|&(polygon, _)| polygon).collect();
+        error_polygons.dedup();
+        let locations = error_edges.iter()
+                                   .map(|&(polygon, vertex)| Location::Vertex(polygon, vertex, self.polygons[polygon].vertices[vertex]))
+                                   .collect();
+        vec![Finding::error(TopologyError::IntersectingPolygons(error_polygons), locations)]
     }
 
-    /// Calculate integrity sums for level.
-    fn calculate_integrity_sums (&mut self, valid_topology: bool) {
+    /// Sum of polygon, object and picture positions used in integrity sums.
+    fn integrity_sum (&self) -> f64 {
         let mut pol_sum = 0_f64;
         let mut obj_sum = 0_f64;
         let mut pic_sum = 0_f64;
 
-        for poly in &self.polygons {
+        for poly in self.polygons.iter().filter(|poly| self.writes_polygon(poly)) {
             for vertex in &poly.vertices {
                 pol_sum += vertex.x + vertex.y;
             }
@@ -810,19 +1379,68 @@ impl Level {
             obj_sum += obj.position.x + obj.position.y + (obj_type as f64);
         }
 
-        for pic in &self.pictures {
-            pic_sum += pic.position.x + pic.position.y;
+        if self.version == Version::Elma {
+            for pic in &self.pictures {
+                pic_sum += pic.position.x + pic.position.y;
+            }
         }
 
-        let sum = (pol_sum + obj_sum + pic_sum) * 3247.764325643;
+        (pol_sum + obj_sum + pic_sum) * 3247.764325643
+    }
+
+    /// Calculate integrity sums for level.
+    fn calculate_integrity_sums<R: RandomSource> (&mut self, valid_topology: bool, rng: &mut R) {
+        let sum = self.integrity_sum();
         self.integrity[0] = sum;
-        self.integrity[1] = (random::<u32>() % 5871) as f64 + 11877. - sum;
+        self.integrity[1] = (rng.next_u32() % 5871) as f64 + 11877. - sum;
         if valid_topology {
-            self.integrity[2] = (random::<u32>() % 5871) as f64 + 11877. - sum;
+            self.integrity[2] = (rng.next_u32() % 5871) as f64 + 11877. - sum;
+        } else {
+            self.integrity[2] = (rng.next_u32() % 4982) as f64 + 20961. - sum;
+        }
+        self.integrity[3] = match self.version {
+            Version::Elma => (rng.next_u32() % 6102) as f64 + 12112. - sum,
+            Version::Across => ACROSS_INTEGRITY_3 - sum
+        };
+    }
+
+    /// Checks the integrity sums against the level, like the game does when loading it, and
+    /// returns whether the level was saved with valid topology. Levels with edited geometry
+    /// but old integrity sums give `ElmaError::InvalidIntegrity` with the index of the first
+    /// mismatching sum. The last sum of Across levels isn't checked, since its valid range is
+    /// only known from a single level.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use elma::lev::{ Level, TopologyStatus };
+    /// let mut level = Level::load("tests/assets/levels/test_1.lev").unwrap();
+    /// assert_eq!(level.verify_integrity(), Ok(TopologyStatus::Ok));
+    /// level.objects[0].position.x += 1.;
+    /// assert_eq!(level.verify_integrity(), Err(elma::ElmaError::InvalidIntegrity(0)));
+    /// ```
+    pub fn verify_integrity (&self) -> Result<TopologyStatus, ElmaError> {
+        let sum = self.integrity_sum();
+        // Sums are compared with some tolerance, since other editors may add the positions
+        // up in another order.
+        let in_range = |index: usize, min: f64, count: f64| {
+            let value = self.integrity[index] + sum;
+            value > min - 0.001 && value < min + count - 1. + 0.001
+        };
+
+        if (self.integrity[0] - sum).abs() > 0.001 { return Err(ElmaError::InvalidIntegrity(0)) }
+        if !in_range(1, 11877., 5871.) { return Err(ElmaError::InvalidIntegrity(1)) }
+        let status = if in_range(2, 11877., 5871.) {
+            TopologyStatus::Ok
+        } else if in_range(2, 20961., 4982.) {
+            TopologyStatus::Error
         } else {
-            self.integrity[2] = (random::<u32>() % 4982) as f64 + 20961. - sum;
+            return Err(ElmaError::InvalidIntegrity(2))
+        };
+        if self.version == Version::Elma && !in_range(3, 12112., 6102.) {
+            return Err(ElmaError::InvalidIntegrity(3))
         }
-        self.integrity[3] = (random::<u32>() % 6102) as f64 + 12112. - sum;
+        Ok(status)
     }
 
     /// Converts all struct fields into raw binary form and returns the raw data.
@@ -842,6 +1460,12 @@ impl Level {
         Ok(self.raw.clone())
     }
 
+    /// Like `get_raw`, but draws the random parts of the integrity sums from `rng`.
+    pub fn get_raw_with_rng<R: RandomSource> (&mut self, top10: bool, rng: &mut R) -> Result<Vec<u8>, ElmaError> {
+        self.update_with_rng(top10, rng)?;
+        Ok(self.raw.clone())
+    }
+
     /// Generate a random link number. When you save a level, it will keep the original link
     /// number unless explicitly changed manually or by running this function before saving.
     ///
@@ -853,7 +1477,12 @@ impl Level {
     /// level.save("newlink.lev", false).unwrap();
     /// ```
     pub fn generate_link (&mut self) {
-        self.link = random::<u32>();
+        self.generate_link_with_rng(&mut ThreadRandom);
+    }
+
+    /// Generate a link number drawn from `rng`.
+    pub fn generate_link_with_rng<R: RandomSource> (&mut self, rng: &mut R) {
+        self.link = rng.next_u32();
     }
 
     /// Saves level as a file.
@@ -870,9 +1499,43 @@ impl Level {
     /// level.save("newlevel.lev", false).unwrap();
     /// ```
     pub fn save<P: AsRef<Path>> (&mut self, filename: P, top10: bool) -> Result<(), ElmaError> {
-        self.update(top10)?;
-        let mut file = File::create(filename)?;
-        file.write_all(&self.raw)?;
+        self.write_to(File::create(filename)?, top10)
+    }
+
+    /// Writes the level to any writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where to write the level data.
+    /// * `top10` - Specifies whether to keep the top10 list (true), or write an empty list (false).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut level = elma::lev::Level::new();
+    /// let mut buffer = vec![];
+    /// level.write_to(&mut buffer, false).unwrap();
+    /// assert_eq!(buffer, level.raw);
+    /// ```
+    pub fn write_to<W: Write> (&mut self, writer: W, top10: bool) -> Result<(), ElmaError> {
+        self.write_to_with_rng(writer, top10, &mut ThreadRandom)
+    }
+
+    /// Like `write_to`, but draws the random parts of the integrity sums from `rng`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut level = elma::lev::Level::new_with_rng(&mut elma::ZeroRng);
+    /// let mut first = vec![];
+    /// let mut second = vec![];
+    /// level.write_to_with_rng(&mut first, false, &mut elma::ZeroRng).unwrap();
+    /// level.write_to_with_rng(&mut second, false, &mut elma::ZeroRng).unwrap();
+    /// assert_eq!(first, second);
+    /// ```
+    pub fn write_to_with_rng<W: Write, R: RandomSource> (&mut self, mut writer: W, top10: bool, rng: &mut R) -> Result<(), ElmaError> {
+        self.update_with_rng(top10, rng)?;
+        writer.write_all(&self.raw)?;
         Ok(())
     }
 }
@@ -886,7 +1549,7 @@ pub fn crypt_top10 (top10_data: &[u8]) -> Vec<u8> {
     let mut ebp8: i16 = 0x15;
     let mut ebp10: i16 = 0x2637;
 
-    for mut t in top10.iter_mut().take(688) {
+    for t in top10.iter_mut().take(688) {
         *t ^= (ebp8 & 0xFF) as u8;
         ebp10 = ebp10.wrapping_add((ebp8.wrapping_rem(0xD3D)).wrapping_mul(0xD3D));
         ebp8 = ebp10.wrapping_mul(0x1F).wrapping_add(0xD3D);
@@ -898,7 +1561,9 @@ pub fn crypt_top10 (top10_data: &[u8]) -> Vec<u8> {
 /// Parse top10 lists and return a vector of `ListEntry`s
 pub fn parse_top10 (top10: &[u8]) -> Result<Vec<ListEntry>, ElmaError> {
     let mut list: Vec<ListEntry> = vec![];
-    let times = LittleEndian::read_i32(&top10[0..4]);
+    if top10.len() < 344 { return Err(ElmaError::UnexpectedEnd) }
+    // A list holds at most 10 entries, so corrupt counts are clamped.
+    let times = LittleEndian::read_i32(&top10[0..4]).clamp(0, 10);
     for n in 0..times {
         let time_offset = (4 + n * 4) as usize;
         let time_end = time_offset + 4;
@@ -911,7 +1576,7 @@ pub fn parse_top10 (top10: &[u8]) -> Result<Vec<ListEntry>, ElmaError> {
         let name_2 = &top10[name_2_offset..name_2_end];
         let time = &top10[time_offset..time_end];
         list.push(ListEntry {
-            time: LittleEndian::read_i32(time),
+            time: Time::from_hundredths(LittleEndian::read_i32(time)),
             name_1: trim_string(name_1)?,
             name_2: trim_string(name_2)?
         });
@@ -919,9 +1584,110 @@ pub fn parse_top10 (top10: &[u8]) -> Result<Vec<ListEntry>, ElmaError> {
     Ok(list)
 }
 
+/// Write a top10 list, returning the 344 bytes of unencrypted data. Only the first 10
+/// entries are written.
+pub fn write_top10 (list: &[ListEntry]) -> Result<Vec<u8>, ElmaError> {
+    let mut bytes: Vec<u8> = vec![];
+
+    let times_count = list.len();
+    bytes.write_i32::<LittleEndian>(if 10 < times_count { 10 } else { times_count } as i32)?;
+    let mut times = [0_i32;10];
+    let mut names_1 = vec![];
+    let mut names_2 = vec![];
+    for (n, entry) in list.iter().enumerate() {
+        if n < 10 {
+            times[n] = i32::try_from(entry.time.hundredths()).map_err(|_| ElmaError::InvalidTimeFormat)?;
+            names_1.extend_from_slice(&string_null_pad(&entry.name_1, 15)?);
+            names_2.extend_from_slice(&string_null_pad(&entry.name_2, 15)?);
+        }
+    }
+    // Pad with null bytes if less than 10 entries.
+    if times_count < 10 {
+        for _ in 0..10 - times_count {
+            names_1.extend_from_slice(&[0u8;15]);
+            names_2.extend_from_slice(&[0u8;15]);
+        }
+    }
+
+    for time in &times {
+        bytes.write_i32::<LittleEndian>(*time)?;
+    }
+
+    bytes.extend_from_slice(&names_1);
+    bytes.extend_from_slice(&names_2);
+    Ok(bytes)
+}
+
+/// Bounding box of points, each extended by a radius.
+fn bounding_box_of<'a, I: Iterator<Item = (&'a Position<f64>, f64)>> (points: I) -> Option<[Position<f64>; 4]> {
+    let mut bounds: Option<(f64, f64, f64, f64)> = None;
+    for (point, radius) in points {
+        let (min_x, max_x, min_y, max_y) = bounds.unwrap_or((f64::MAX, f64::MIN, f64::MAX, f64::MIN));
+        bounds = Some((min_x.min(point.x - radius), max_x.max(point.x + radius),
+                       min_y.min(point.y - radius), max_y.max(point.y + radius)));
+    }
+
+    bounds.map(|(min_x, max_x, min_y, max_y)| {
+        [Position { x: min_x, y: max_y },
+         Position { x: max_x, y: max_y },
+         Position { x: min_x, y: min_y },
+         Position { x: max_x, y: min_y }]
+    })
+}
+
+/// Returns the first error of a topology report, ignoring warnings.
+fn first_error (findings: Vec<Finding>) -> Result<(), TopologyError> {
+    match findings.into_iter().find(|finding| finding.severity == Severity::Error) {
+        Some(finding) => Err(finding.error),
+        None => Ok(())
+    }
+}
+
+/// Shortest distance from a point to a line segment.
+fn distance_to_segment (point: &Position<f64>, start: &Position<f64>, end: &Position<f64>) -> f64 {
+    let segment = (end.x - start.x, end.y - start.y);
+    let length = segment.0 * segment.0 + segment.1 * segment.1;
+    let t = if length > 0_f64 {
+        (((point.x - start.x) * segment.0 + (point.y - start.y) * segment.1) / length).clamp(0_f64, 1_f64)
+    } else {
+        0_f64
+    };
+    let closest = (start.x + t * segment.0, start.y + t * segment.1);
+    ((point.x - closest.0).powi(2) + (point.y - closest.1).powi(2)).sqrt()
+}
+
 // Original code by Peter Kelley <pgkelley4@gmail.com> from:
 // https://github.com/pgkelley4/line-segments-intersect/blob/39d4425b2868fd8fc26172d94132215568c70523/js/line-segments-intersect.js
-fn do_line_segment_intersect(seg_one_start: Position<f64>, seg_one_end: Position<f64>,
-                             seg_two_start: Position<f64>, seg_two_end: Position<f64>) -> bool {
-    true
+fn do_line_segment_intersect(seg_one_start: &Position<f64>, seg_one_end: &Position<f64>,
+                             seg_two_start: &Position<f64>, seg_two_end: &Position<f64>) -> bool {
+    let cross_product = |a: (f64, f64), b: (f64, f64)| a.0 * b.1 - a.1 * b.0;
+    let r = (seg_one_end.x - seg_one_start.x, seg_one_end.y - seg_one_start.y);
+    let s = (seg_two_end.x - seg_two_start.x, seg_two_end.y - seg_two_start.y);
+    let start_difference = (seg_two_start.x - seg_one_start.x, seg_two_start.y - seg_one_start.y);
+
+    let u_numerator = cross_product(start_difference, r);
+    let denominator = cross_product(r, s);
+
+    if u_numerator == 0_f64 && denominator == 0_f64 {
+        // Collinear, so check if they share an endpoint.
+        if seg_one_start == seg_two_start || seg_one_start == seg_two_end ||
+           seg_one_end == seg_two_start || seg_one_end == seg_two_end {
+            return true
+        }
+        // Otherwise check if they overlap on either axis.
+        let all_equal = |a: bool, b: bool, c: bool, d: bool| a == b && a == c && a == d;
+        return !all_equal(seg_two_start.x - seg_one_start.x < 0_f64, seg_two_start.x - seg_one_end.x < 0_f64,
+                          seg_two_end.x - seg_one_start.x < 0_f64, seg_two_end.x - seg_one_end.x < 0_f64) ||
+               !all_equal(seg_two_start.y - seg_one_start.y < 0_f64, seg_two_start.y - seg_one_end.y < 0_f64,
+                          seg_two_end.y - seg_one_start.y < 0_f64, seg_two_end.y - seg_one_end.y < 0_f64)
+    }
+
+    if denominator == 0_f64 {
+        // Parallel.
+        return false
+    }
+
+    let u = u_numerator / denominator;
+    let t = cross_product(start_difference, s) / denominator;
+    (0_f64..=1_f64).contains(&t) && (0_f64..=1_f64).contains(&u)
 }
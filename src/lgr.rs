@@ -0,0 +1,347 @@
+use std::io::{ Read, Write };
+use std::fs::File;
+use std::path::Path;
+use byteorder::{ ReadBytesExt, WriteBytesExt, LittleEndian };
+use super::{ checked_split_at, split_items, trim_string, string_null_pad, ElmaError };
+use super::lev::Clip;
+use super::pcx::Pcx;
+
+// Version number of the picture list.
+const PICTURE_LIST_VERSION: i32 = 1002;
+// Magic arbitrary number signifying end-of-file in LGR file.
+const LGR_EOF: i32 = 0x0B2E05E7;
+
+/// Type of picture in the picture list.
+#[derive(Debug, Default, PartialEq)]
+pub enum PictureType {
+    /// Normal picture, usable as `Picture::name`.
+    #[default]
+    Normal,
+    /// Texture, usable as `Picture::texture`.
+    Texture,
+    /// Mask, usable as `Picture::mask`.
+    Mask
+}
+
+/// Transparent colour of a picture.
+#[derive(Debug, Default, PartialEq)]
+pub enum Transparency {
+    /// No transparency.
+    Solid,
+    /// Palette index 0 is transparent.
+    Palette,
+    /// Colour of the top-left pixel is transparent.
+    #[default]
+    TopLeft,
+    /// Colour of the top-right pixel is transparent.
+    TopRight,
+    /// Colour of the bottom-left pixel is transparent.
+    BottomLeft,
+    /// Colour of the bottom-right pixel is transparent.
+    BottomRight
+}
+
+/// Picture list entry, describing how an image can be used in levels.
+#[derive(Debug, Default, PartialEq)]
+pub struct PictureData {
+    /// Picture name, without extension.
+    pub name: String,
+    /// Picture type.
+    pub picture_type: PictureType,
+    /// Default Z-distance.
+    pub distance: i32,
+    /// Default clipping.
+    pub clip: Clip,
+    /// Transparency.
+    pub transparency: Transparency
+}
+
+impl PictureData {
+    /// Creates a new picture list entry with default values.
+    pub fn new (name: &str) -> Self {
+        PictureData {
+            name: String::from(name),
+            picture_type: PictureType::default(),
+            distance: 600,
+            clip: Clip::default(),
+            transparency: Transparency::default()
+        }
+    }
+}
+
+/// Image embedded in an LGR file.
+#[derive(Debug, Default, PartialEq)]
+pub struct LgrImage {
+    /// File name, including extension.
+    pub name: String,
+    /// Four bytes after the name with unknown meaning, kept so images are written back unchanged.
+    pub unknown: [u8; 4],
+    /// Raw PCX file data.
+    pub data: Vec<u8>
+}
+
+impl LgrImage {
+    /// Decodes the PCX data of the image.
+    pub fn decode (&self) -> Result<Pcx, ElmaError> {
+        Pcx::decode(&self.data)
+    }
+}
+
+/// LGR struct that contains the picture list and all images.
+#[derive(Debug, Default, PartialEq)]
+pub struct Lgr {
+    /// Raw binary data of a loaded or saved LGR.
+    pub raw: Vec<u8>,
+    /// Picture list (pictures.lst).
+    pub pictures: Vec<PictureData>,
+    /// Embedded images.
+    pub images: Vec<LgrImage>
+}
+
+impl Lgr {
+    /// Returns a new empty `Lgr` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let lgr = elma::lgr::Lgr::new();
+    /// ```
+    pub fn new () -> Self {
+        Lgr {
+            raw: vec![],
+            pictures: vec![],
+            images: vec![]
+        }
+    }
+
+    /// Loads an LGR file and returns a `Lgr` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// let lgr = elma::lgr::Lgr::load("default.lgr").unwrap();
+    /// ```
+    pub fn load<P: AsRef<Path>> (filename: P) -> Result<Self, ElmaError> {
+        Lgr::from_reader(File::open(filename)?)
+    }
+
+    /// Reads an LGR from any reader and returns a `Lgr` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let file = std::fs::File::open("tests/assets/lgr/constructed.lgr").unwrap();
+    /// let lgr = elma::lgr::Lgr::from_reader(file).unwrap();
+    /// ```
+    pub fn from_reader<R: Read> (mut reader: R) -> Result<Self, ElmaError> {
+        let mut buffer = vec![];
+        reader.read_to_end(&mut buffer)?;
+        Lgr::from_bytes(buffer)
+    }
+
+    /// Parses LGR data already in memory and returns a `Lgr` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut lgr = elma::lgr::Lgr::new();
+    /// lgr.pictures.push(elma::lgr::PictureData::new("barrel"));
+    /// lgr.update().unwrap();
+    /// let parsed = elma::lgr::Lgr::from_bytes(lgr.raw.clone()).unwrap();
+    /// assert_eq!(parsed.pictures, lgr.pictures);
+    /// ```
+    pub fn from_bytes<B: Into<Vec<u8>>> (data: B) -> Result<Self, ElmaError> {
+        let mut lgr = Lgr::new();
+        lgr.raw = data.into();
+        lgr.parse_lgr()?;
+        Ok(lgr)
+    }
+
+    /// Parses the raw binary data into `Lgr` struct fields.
+    fn parse_lgr (&mut self) -> Result<(), ElmaError> {
+        let remaining = self.raw.as_slice();
+
+        // Version.
+        let (version, mut remaining) = checked_split_at(remaining, 5)?;
+        if version != b"LGR12" { return Err(ElmaError::InvalidLgrFile) }
+
+        // Number of images.
+        let image_count = remaining.read_i32::<LittleEndian>()?;
+
+        // Picture list.
+        if remaining.read_i32::<LittleEndian>()? != PICTURE_LIST_VERSION {
+            return Err(ElmaError::InvalidLgrFile)
+        }
+        let picture_count = remaining.read_i32::<LittleEndian>()?;
+        if picture_count < 0 { return Err(ElmaError::InvalidLgrFile) }
+        let picture_count = picture_count as usize;
+        let (names, remaining) = split_items(remaining, picture_count, 10)?;
+        let (mut types, remaining) = split_items(remaining, picture_count, 4)?;
+        let (mut distances, remaining) = split_items(remaining, picture_count, 4)?;
+        let (mut clippings, remaining) = split_items(remaining, picture_count, 4)?;
+        let (mut transparencies, mut remaining) = split_items(remaining, picture_count, 4)?;
+        self.pictures = vec![];
+        for name in names.chunks(10) {
+            let picture_type = match types.read_i32::<LittleEndian>()? {
+                100 => PictureType::Normal,
+                101 => PictureType::Texture,
+                102 => PictureType::Mask,
+                other => return Err(ElmaError::InvalidPictureType(other))
+            };
+            let distance = distances.read_i32::<LittleEndian>()?;
+            let clip = match clippings.read_i32::<LittleEndian>()? {
+                0 => Clip::Unclipped,
+                1 => Clip::Ground,
+                2 => Clip::Sky,
+                other => return Err(ElmaError::InvalidClipping(other))
+            };
+            let transparency = match transparencies.read_i32::<LittleEndian>()? {
+                10 => Transparency::Solid,
+                11 => Transparency::Palette,
+                12 => Transparency::TopLeft,
+                13 => Transparency::TopRight,
+                14 => Transparency::BottomLeft,
+                15 => Transparency::BottomRight,
+                other => return Err(ElmaError::InvalidTransparency(other))
+            };
+
+            self.pictures.push(PictureData {
+                name: trim_string(name)?,
+                picture_type,
+                distance,
+                clip,
+                transparency
+            });
+        }
+
+        // Images.
+        self.images = vec![];
+        for _ in 0..image_count {
+            let (name, temp_remaining) = checked_split_at(remaining, 20)?;
+            let name = trim_string(name)?;
+            // Unknown.
+            let (unknown, mut temp_remaining) = checked_split_at(temp_remaining, 4)?;
+            let length = temp_remaining.read_i32::<LittleEndian>()?;
+            if length < 0 { return Err(ElmaError::InvalidLgrFile) }
+            let (data, temp_remaining) = checked_split_at(temp_remaining, length as usize)?;
+            remaining = temp_remaining;
+
+            self.images.push(LgrImage {
+                name,
+                unknown: [unknown[0], unknown[1], unknown[2], unknown[3]],
+                data: data.to_vec()
+            });
+        }
+
+        // EOF marker expected at this point.
+        let expected = remaining.read_i32::<LittleEndian>()?;
+        if expected != LGR_EOF { return Err(ElmaError::EOFMismatch) }
+
+        Ok(())
+    }
+
+    /// Combines the `Lgr` struct fields to generate the raw binary data. Called automatically
+    /// when using the `save` or `write_to` methods.
+    pub fn update (&mut self) -> Result<(), ElmaError> {
+        let mut bytes = vec![];
+
+        // Version.
+        bytes.extend_from_slice(b"LGR12");
+        // Number of images.
+        bytes.write_i32::<LittleEndian>(self.images.len() as i32)?;
+
+        // Picture list, stored one field at a time.
+        bytes.write_i32::<LittleEndian>(PICTURE_LIST_VERSION)?;
+        bytes.write_i32::<LittleEndian>(self.pictures.len() as i32)?;
+        for picture in &self.pictures {
+            bytes.extend_from_slice(&string_null_pad(&picture.name, 10)?);
+        }
+        for picture in &self.pictures {
+            bytes.write_i32::<LittleEndian>(match picture.picture_type {
+                PictureType::Normal => 100,
+                PictureType::Texture => 101,
+                PictureType::Mask => 102
+            })?;
+        }
+        for picture in &self.pictures {
+            bytes.write_i32::<LittleEndian>(picture.distance)?;
+        }
+        for picture in &self.pictures {
+            bytes.write_i32::<LittleEndian>(match picture.clip {
+                Clip::Unclipped => 0,
+                Clip::Ground => 1,
+                Clip::Sky => 2
+            })?;
+        }
+        for picture in &self.pictures {
+            bytes.write_i32::<LittleEndian>(match picture.transparency {
+                Transparency::Solid => 10,
+                Transparency::Palette => 11,
+                Transparency::TopLeft => 12,
+                Transparency::TopRight => 13,
+                Transparency::BottomLeft => 14,
+                Transparency::BottomRight => 15
+            })?;
+        }
+
+        // Images.
+        for image in &self.images {
+            bytes.extend_from_slice(&string_null_pad(&image.name, 20)?);
+            bytes.extend_from_slice(&image.unknown);
+            bytes.write_i32::<LittleEndian>(image.data.len() as i32)?;
+            bytes.extend_from_slice(&image.data);
+        }
+
+        // EOF marker.
+        bytes.write_i32::<LittleEndian>(LGR_EOF)?;
+
+        self.raw = bytes;
+        Ok(())
+    }
+
+    /// Saves LGR as a file.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// let mut lgr = elma::lgr::Lgr::new();
+    /// lgr.save("custom.lgr").unwrap();
+    /// ```
+    pub fn save<P: AsRef<Path>> (&mut self, filename: P) -> Result<(), ElmaError> {
+        self.write_to(File::create(filename)?)
+    }
+
+    /// Writes the LGR to any writer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut lgr = elma::lgr::Lgr::new();
+    /// let mut buffer = vec![];
+    /// lgr.write_to(&mut buffer).unwrap();
+    /// assert_eq!(buffer, lgr.raw);
+    /// ```
+    pub fn write_to<W: Write> (&mut self, mut writer: W) -> Result<(), ElmaError> {
+        self.update()?;
+        writer.write_all(&self.raw)?;
+        Ok(())
+    }
+
+    /// Returns the picture list entry with the given name, ignoring case like the game does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut lgr = elma::lgr::Lgr::new();
+    /// lgr.pictures.push(elma::lgr::PictureData::new("barrel"));
+    /// assert!(lgr.picture("BARREL").is_some());
+    /// ```
+    pub fn picture (&self, name: &str) -> Option<&PictureData> {
+        self.pictures.iter().find(|picture| picture.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the image with the given file name, ignoring case like the game does.
+    pub fn image (&self, name: &str) -> Option<&LgrImage> {
+        self.images.iter().find(|image| image.name.eq_ignore_ascii_case(name))
+    }
+}
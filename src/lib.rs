@@ -6,19 +6,30 @@
 extern crate byteorder;
 extern crate rand;
 
-use std::{io, str, string};
-use std::ascii::AsciiExt;
+use std::{error, fmt, io, str, string};
 
+/// Analyse replays.
+pub mod analysis;
 /// Read and write Elasto Mania level files.
 pub mod lev;
+/// Read and write Elasto Mania LGR files.
+pub mod lgr;
+/// Decode and encode PCX images used in LGR files.
+pub mod pcx;
 /// Read and write Elasto Mania replay files.
 pub mod rec;
+/// Read and write Elasto Mania state.dat files.
+pub mod state;
+/// Times in top10 lists and replays.
+pub mod time;
 
 /// General errors.
 #[derive(Debug, PartialEq)]
 pub enum ElmaError {
     /// Across files are not supported.
     AcrossUnsupported,
+    /// Across replays can't hold a second player.
+    AcrossMultiPlayer,
     /// Not a level file.
     InvalidLevelFile,
     /// Invalid gravity value.
@@ -27,6 +38,8 @@ pub enum ElmaError {
     InvalidObject(i32),
     /// Invalid clipping value.
     InvalidClipping(i32),
+    /// Integrity sum with this index doesn't match the level.
+    InvalidIntegrity(usize),
     /// End-of-data marker mismatch.
     EODMismatch,
     /// End-of-file marker mismatch.
@@ -35,21 +48,106 @@ pub enum ElmaError {
     InvalidEvent(u8),
     /// End-of-replay marker mismatch.
     EORMismatch,
+    /// Not a replay file.
+    InvalidReplayFile,
+    /// Not a state.dat file.
+    InvalidStateFile,
+    /// Not an LGR file.
+    InvalidLgrFile,
+    /// Invalid picture type value.
+    InvalidPictureType(i32),
+    /// Invalid transparency value.
+    InvalidTransparency(i32),
+    /// Not a supported PCX image.
+    InvalidPcxFile,
     /// Invalid time format.
     InvalidTimeFormat,
     /// Too short padding.
     PaddingTooShort(isize),
     /// String contains non-ASCII characters.
     NonASCII,
+    /// Data ended before the file was completely read.
+    UnexpectedEnd,
     /// Input/output errors from std::io use.
     Io(std::io::ErrorKind),
     /// String errors from std::String.
     StringFromUtf8(usize),
+    /// Error while parsing a file, with where it happened.
+    Parse {
+        /// Byte offset from the start of the file.
+        offset: usize,
+        /// Logical location, like "object #17" or "player 2 event #203". Indexes start at 0.
+        location: String,
+        /// The actual error.
+        error: Box<ElmaError>
+    },
+}
+
+impl ElmaError {
+    /// Returns the underlying error, without the location of parse errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let error = elma::lev::Level::load("tests/assets/levels/invalid_obj.lev").unwrap_err();
+    /// assert_eq!(error.root_cause(), &elma::ElmaError::InvalidObject(6));
+    /// ```
+    pub fn root_cause (&self) -> &ElmaError {
+        match *self {
+            ElmaError::Parse { ref error, .. } => error.root_cause(),
+            ref error => error
+        }
+    }
+}
+
+impl fmt::Display for ElmaError {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ElmaError::AcrossUnsupported => write!(f, "Across files are not supported"),
+            ElmaError::AcrossMultiPlayer => write!(f, "Across replays can't hold a second player"),
+            ElmaError::InvalidLevelFile => write!(f, "not a level file"),
+            ElmaError::InvalidGravity(value) => write!(f, "invalid gravity value {}", value),
+            ElmaError::InvalidObject(value) => write!(f, "invalid object value {}", value),
+            ElmaError::InvalidClipping(value) => write!(f, "invalid clipping value {}", value),
+            ElmaError::InvalidIntegrity(index) => write!(f, "invalid integrity sum {}", index),
+            ElmaError::EODMismatch => write!(f, "end-of-data marker mismatch"),
+            ElmaError::EOFMismatch => write!(f, "end-of-file marker mismatch"),
+            ElmaError::InvalidEvent(value) => write!(f, "invalid event value {}", value),
+            ElmaError::EORMismatch => write!(f, "end-of-replay marker mismatch"),
+            ElmaError::InvalidReplayFile => write!(f, "not a replay file"),
+            ElmaError::InvalidStateFile => write!(f, "not a state.dat file"),
+            ElmaError::InvalidLgrFile => write!(f, "not an LGR file"),
+            ElmaError::InvalidPictureType(value) => write!(f, "invalid picture type value {}", value),
+            ElmaError::InvalidTransparency(value) => write!(f, "invalid transparency value {}", value),
+            ElmaError::InvalidPcxFile => write!(f, "not a supported PCX image"),
+            ElmaError::InvalidTimeFormat => write!(f, "invalid time format"),
+            ElmaError::PaddingTooShort(excess) => write!(f, "string is {} bytes too long for padding", excess),
+            ElmaError::NonASCII => write!(f, "string contains non-ASCII characters"),
+            ElmaError::UnexpectedEnd => write!(f, "unexpected end of data"),
+            ElmaError::Io(kind) => write!(f, "I/O error: {:?}", kind),
+            ElmaError::StringFromUtf8(valid_up_to) => write!(f, "invalid UTF-8 after {} bytes", valid_up_to),
+            ElmaError::Parse { offset, ref location, ref error } => {
+                write!(f, "{} at offset {} (0x{:X}): {}", location, offset, offset, error)
+            }
+        }
+    }
+}
+
+impl error::Error for ElmaError {
+    fn source (&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ElmaError::Parse { ref error, .. } => Some(&**error),
+            _ => None
+        }
+    }
 }
 
 impl From<io::Error> for ElmaError {
     fn from(err: io::Error) -> ElmaError {
-        ElmaError::Io(err.kind())
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ElmaError::UnexpectedEnd,
+            kind => ElmaError::Io(kind)
+        }
     }
 }
 
@@ -59,13 +157,23 @@ impl From<string::FromUtf8Error> for ElmaError {
     }
 }
 
+/// Game version, shared by levels and replays.
+#[derive(Debug, Default, PartialEq)]
+pub enum Version {
+    /// Action SuperCross, older version of Elma.
+    Across,
+    /// Elasto Mania, current active version.
+    #[default]
+    Elma
+}
+
 /// Shared position struct used in both sub-modules.
 ///
 /// # Examples
 /// ```
 /// let vertex = elma::Position { x: 23.1928_f64, y: -199.200019_f64 };
 /// ```
-#[derive(Debug, Default, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Position<T> {
     /// X-position.
     pub x: T,
@@ -73,6 +181,52 @@ pub struct Position<T> {
     pub y: T
 }
 
+/// Random source that always returns zero. Pass it to the `_with_rng` methods to fill
+/// link numbers and integrity sums deterministically.
+///
+/// # Examples
+///
+/// ```
+/// let mut level = elma::lev::Level::new_with_rng(&mut elma::ZeroRng);
+/// let raw = level.get_raw_with_rng(false, &mut elma::ZeroRng).unwrap();
+/// assert_eq!(raw, elma::lev::Level::new_with_rng(&mut elma::ZeroRng).get_raw_with_rng(false, &mut elma::ZeroRng).unwrap());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ZeroRng;
+
+impl RandomSource for ZeroRng {
+    fn next_u32 (&mut self) -> u32 { 0 }
+}
+
+/// Source of the random link numbers and integrity sums written by the `_with_rng` methods.
+///
+/// # Examples
+///
+/// ```
+/// struct Counter(u32);
+///
+/// impl elma::RandomSource for Counter {
+///     fn next_u32 (&mut self) -> u32 {
+///         self.0 += 1;
+///         self.0
+///     }
+/// }
+///
+/// let level = elma::lev::Level::new_with_rng(&mut Counter(41));
+/// assert_eq!(level.link, 42);
+/// ```
+pub trait RandomSource {
+    /// Returns the next random number.
+    fn next_u32 (&mut self) -> u32;
+}
+
+// Thread-local random numbers, used by the methods without `_with_rng`.
+struct ThreadRandom;
+
+impl RandomSource for ThreadRandom {
+    fn next_u32 (&mut self) -> u32 { rand::random() }
+}
+
 /// Trims trailing bytes after and including null byte.
 ///
 /// # Examples
@@ -85,7 +239,7 @@ pub struct Position<T> {
 /// assert_eq!(trimmed, "Elma");
 /// ```
 pub fn trim_string (data: &[u8]) -> Result<String, ElmaError> {
-    let bytes: Vec<u8> = data.into_iter()
+    let bytes: Vec<u8> = data.iter()
                              .take_while(|&&d| d != 0)
                              .cloned()
                              .collect();
@@ -99,7 +253,7 @@ pub fn trim_string (data: &[u8]) -> Result<String, ElmaError> {
 /// # Examples
 /// Thanks to the genious data structure in Elma files, the best times in a level are represented
 /// visually as a string, but stored as a i32. This function will convert the i32 time to a string
-/// formatted as "00:00,00".
+/// formatted as "00:00,00". Use `time::Time` for times in hundredths.
 ///
 /// ```
 /// let time: i32 = 2039;
@@ -107,27 +261,61 @@ pub fn trim_string (data: &[u8]) -> Result<String, ElmaError> {
 /// assert_eq!("00:20,39", formatted);
 /// ```
 pub fn time_format (time: i32) -> Result<String, ElmaError> {
-    let time = time.to_string().into_bytes();
-    let mut formatted = String::from("00:00,00").into_bytes();
-
-    // If input time is longer than 6 characters, return max time.
-    if time.len() > 6 { return Ok(String::from("59:59,99")); }
-
-    let mut n = 7;
-    for byte in time.iter().rev() {
-        // If first digit of minutes or seconds are over 5, return error.
-        if ((n == 3) || (n == 0)) && (*byte > 53) { return Err(ElmaError::InvalidTimeFormat); }
-
-        formatted[n] = *byte;
-        if n == 6 || n == 3 {
-            n -= 2;
-        } else if n > 0 {
-            n -= 1;
-        }
+    // Times longer than 6 characters are past 59:59,99 in any unit, which `Time` shows as the max time.
+    if time > 999_999 { return Ok(time::Time::from_hundredths(time).to_string()) }
+    if time < 0 { return Err(ElmaError::InvalidTimeFormat) }
+
+    let digits = format!("{:06}", time);
+    let time: time::Time = format!("{}:{},{}", &digits[0..2], &digits[2..4], &digits[4..6]).parse()?;
+    Ok(time.to_string())
+}
+
+/// Keeps track of what is being parsed, to add the location to parse errors.
+struct ParseContext {
+    offset: usize,
+    section: &'static str,
+    index: Option<usize>
+}
+
+impl ParseContext {
+    fn new () -> Self {
+        ParseContext { offset: 0, section: "header", index: None }
     }
 
-    let time = String::from_utf8(formatted)?;
-    Ok(time)
+    /// Starts parsing a section at `offset`.
+    fn section (&mut self, offset: usize, section: &'static str) {
+        self.offset = offset;
+        self.section = section;
+        self.index = None;
+    }
+
+    /// Starts parsing item number `index` of a section at `offset`.
+    fn item (&mut self, offset: usize, section: &'static str, index: usize) {
+        self.offset = offset;
+        self.section = section;
+        self.index = Some(index);
+    }
+
+    /// Adds the current location to an error.
+    fn wrap (&self, error: ElmaError) -> ElmaError {
+        let location = match self.index {
+            Some(index) => format!("{} #{}", self.section, index),
+            None => self.section.to_string()
+        };
+        ElmaError::Parse { offset: self.offset, location, error: Box::new(error) }
+    }
+}
+
+/// Splits `data` at `mid` like `slice::split_at`, but returns `ElmaError::UnexpectedEnd`
+/// instead of panicking when `data` is too short.
+fn checked_split_at (data: &[u8], mid: usize) -> Result<(&[u8], &[u8]), ElmaError> {
+    if mid > data.len() { return Err(ElmaError::UnexpectedEnd) }
+    Ok(data.split_at(mid))
+}
+
+/// Splits off `count` items of `size` bytes each from the start of `data`.
+fn split_items (data: &[u8], count: usize, size: usize) -> Result<(&[u8], &[u8]), ElmaError> {
+    checked_split_at(data, count.checked_mul(size).ok_or(ElmaError::UnexpectedEnd)?)
 }
 
 /// Pads a string with null bytes.
@@ -148,7 +336,7 @@ pub fn string_null_pad (name: &str, pad: usize) -> Result<Vec<u8>, ElmaError> {
     // first check if string is ASCII
     if !name.is_ascii() { return Err(ElmaError::NonASCII) }
     // padding shorter than string
-    if name.len() > pad { return Err(ElmaError::PaddingTooShort((pad as isize - name.len() as isize) as isize)) }
+    if name.len() > pad { return Err(ElmaError::PaddingTooShort(pad as isize - name.len() as isize)) }
 
     let mut bytes = vec![0u8; pad];
     for (n, char) in name.iter().enumerate() {
@@ -225,3 +413,7 @@ const EMPTY_TOP10: [u8; 688] = [0x15,0x05,0x6A,0xB7,0x89,0xED,0x59,0xC4,0x48,0xF
                                 0x22,0x97,0xC1,0x36,0xAF,0x14,0xC3,0x95,0xD8,0x60,0xE9,0x4C];
 // Magic arbitrary number to signify end of replay file.
 const EOR: i32 = 0x00492F75;
+// Replay header value of Elma replays.
+const ELMA_REC: i32 = 0x83;
+// Replay header value of Across replays.
+const ACROSS_REC: i32 = 0x82;
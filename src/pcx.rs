@@ -0,0 +1,201 @@
+use byteorder::{ ByteOrder, WriteBytesExt, LittleEndian };
+use super::ElmaError;
+use super::lgr::Transparency;
+
+// PCX header size.
+const HEADER_SIZE: usize = 128;
+// Marker preceding the 256-colour palette at the end of the file.
+const PALETTE_MARKER: u8 = 0x0C;
+
+/// 8-bit indexed-colour image with a 256-colour palette, as used in LGR files.
+#[derive(Debug, Default, PartialEq)]
+pub struct Pcx {
+    /// Width in pixels.
+    pub width: u16,
+    /// Height in pixels.
+    pub height: u16,
+    /// Palette indexes, row by row from the top-left pixel.
+    pub pixels: Vec<u8>,
+    /// Palette with 256 RGB colours.
+    pub palette: Vec<[u8; 3]>
+}
+
+impl Pcx {
+    /// Returns a new image filled with palette index 0 and a black palette.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let image = elma::pcx::Pcx::new(48, 48);
+    /// assert_eq!(image.pixels.len(), 48 * 48);
+    /// ```
+    pub fn new (width: u16, height: u16) -> Self {
+        Pcx {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize],
+            palette: vec![[0; 3]; 256]
+        }
+    }
+
+    /// Decodes PCX file data. Only 8-bit images with one colour plane and a 256-colour
+    /// palette are supported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut image = elma::pcx::Pcx::new(3, 2);
+    /// image.pixels = vec![1, 1, 1, 0, 200, 0];
+    /// let decoded = elma::pcx::Pcx::decode(&image.encode().unwrap()).unwrap();
+    /// assert_eq!(decoded, image);
+    /// ```
+    pub fn decode (data: &[u8]) -> Result<Self, ElmaError> {
+        if data.len() < HEADER_SIZE + 769 { return Err(ElmaError::InvalidPcxFile) }
+        let (header, remaining) = data.split_at(HEADER_SIZE);
+
+        // Manufacturer, encoding, bits per pixel and colour planes.
+        if header[0] != 0x0A || header[2] != 1 || header[3] != 8 || header[65] != 1 {
+            return Err(ElmaError::InvalidPcxFile)
+        }
+        let x_min = LittleEndian::read_u16(&header[4..6]);
+        let y_min = LittleEndian::read_u16(&header[6..8]);
+        let x_max = LittleEndian::read_u16(&header[8..10]);
+        let y_max = LittleEndian::read_u16(&header[10..12]);
+        if x_max < x_min || y_max < y_min { return Err(ElmaError::InvalidPcxFile) }
+        let width = x_max as usize - x_min as usize + 1;
+        let height = y_max as usize - y_min as usize + 1;
+        if width > u16::MAX as usize || height > u16::MAX as usize { return Err(ElmaError::InvalidPcxFile) }
+        let bytes_per_line = LittleEndian::read_u16(&header[66..68]) as usize;
+        if bytes_per_line < width { return Err(ElmaError::InvalidPcxFile) }
+
+        // Palette.
+        let (image_data, palette_data) = remaining.split_at(remaining.len() - 769);
+        if palette_data[0] != PALETTE_MARKER { return Err(ElmaError::InvalidPcxFile) }
+        let palette = palette_data[1..].chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+
+        // Run-length encoded scanlines. Runs may continue on the next scanline. Each pair of
+        // bytes decodes to at most 63 bytes, so larger sizes can't be valid.
+        if bytes_per_line * height > image_data.len() / 2 * 63 + 1 {
+            return Err(ElmaError::InvalidPcxFile)
+        }
+        let mut scanlines = Vec::with_capacity(bytes_per_line * height);
+        let mut bytes = image_data.iter();
+        while scanlines.len() < bytes_per_line * height {
+            let byte = *bytes.next().ok_or(ElmaError::InvalidPcxFile)?;
+            if byte & 0xC0 == 0xC0 {
+                let value = *bytes.next().ok_or(ElmaError::InvalidPcxFile)?;
+                for _ in 0..byte & 0x3F {
+                    scanlines.push(value);
+                }
+            } else {
+                scanlines.push(byte);
+            }
+        }
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for line in scanlines.chunks(bytes_per_line).take(height) {
+            pixels.extend_from_slice(&line[0..width]);
+        }
+
+        Ok(Pcx {
+            width: width as u16,
+            height: height as u16,
+            pixels,
+            palette
+        })
+    }
+
+    /// Encodes the image as PCX file data.
+    pub fn encode (&self) -> Result<Vec<u8>, ElmaError> {
+        if self.width == 0 || self.height == 0 ||
+           self.pixels.len() != self.width as usize * self.height as usize ||
+           self.palette.len() > 256 {
+            return Err(ElmaError::InvalidPcxFile)
+        }
+        // Scanlines are padded to an even length, which must still fit the header.
+        let bytes_per_line = (self.width as usize + 1) & !1;
+        if bytes_per_line > u16::MAX as usize { return Err(ElmaError::InvalidPcxFile) }
+
+        let mut bytes = vec![];
+        // Manufacturer, version, encoding and bits per pixel.
+        bytes.extend_from_slice(&[0x0A, 5, 1, 8]);
+        // Image dimensions.
+        bytes.write_u16::<LittleEndian>(0)?;
+        bytes.write_u16::<LittleEndian>(0)?;
+        bytes.write_u16::<LittleEndian>(self.width - 1)?;
+        bytes.write_u16::<LittleEndian>(self.height - 1)?;
+        // Resolution.
+        bytes.write_u16::<LittleEndian>(72)?;
+        bytes.write_u16::<LittleEndian>(72)?;
+        // Unused 16-colour palette and reserved byte.
+        bytes.extend_from_slice(&[0u8; 49]);
+        // Colour planes, bytes per line and palette type.
+        bytes.write_u8(1)?;
+        bytes.write_u16::<LittleEndian>(bytes_per_line as u16)?;
+        bytes.write_u16::<LittleEndian>(1)?;
+        // Screen size and filler.
+        bytes.extend_from_slice(&[0u8; 58]);
+
+        // Run-length encoded scanlines.
+        for row in self.pixels.chunks(self.width as usize) {
+            let mut line = row.to_vec();
+            line.resize(bytes_per_line, 0);
+            let mut n = 0;
+            while n < line.len() {
+                let value = line[n];
+                let mut run = 1;
+                while n + run < line.len() && run < 63 && line[n + run] == value {
+                    run += 1;
+                }
+                if run > 1 || value & 0xC0 == 0xC0 {
+                    bytes.write_u8(0xC0 | run as u8)?;
+                }
+                bytes.write_u8(value)?;
+                n += run;
+            }
+        }
+
+        // Palette, padded to 256 colours.
+        bytes.write_u8(PALETTE_MARKER)?;
+        for n in 0..256 {
+            bytes.extend_from_slice(self.palette.get(n).unwrap_or(&[0; 3]));
+        }
+
+        Ok(bytes)
+    }
+
+    /// Palette index of the transparent colour for a picture list transparency setting.
+    pub fn transparent_index (&self, transparency: &Transparency) -> Option<u8> {
+        let width = self.width as usize;
+        let last_row = self.pixels.len().saturating_sub(width);
+        match *transparency {
+            Transparency::Solid => None,
+            Transparency::Palette => Some(0),
+            Transparency::TopLeft => self.pixels.first().cloned(),
+            Transparency::TopRight => self.pixels.get(width.saturating_sub(1)).cloned(),
+            Transparency::BottomLeft => self.pixels.get(last_row).cloned(),
+            Transparency::BottomRight => self.pixels.last().cloned()
+        }
+    }
+
+    /// Converts the image to RGBA bytes, row by row. Pixels with the `transparent` palette
+    /// index get zero alpha.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut image = elma::pcx::Pcx::new(2, 1);
+    /// image.pixels = vec![0, 1];
+    /// image.palette[1] = [255, 128, 0];
+    /// assert_eq!(image.to_rgba(Some(0)), vec![0, 0, 0, 0, 255, 128, 0, 255]);
+    /// ```
+    pub fn to_rgba (&self, transparent: Option<u8>) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+        for &index in &self.pixels {
+            let rgb = self.palette.get(index as usize).unwrap_or(&[0; 3]);
+            rgba.extend_from_slice(rgb);
+            rgba.push(if Some(index) == transparent { 0 } else { 255 });
+        }
+        rgba
+    }
+}
@@ -1,9 +1,29 @@
+use std::cmp::Ordering;
+use std::error;
+use std::f64::consts::PI;
+use std::fmt;
 use std::io::{ Read, Write };
+use std::ops::RangeInclusive;
 use std::fs::File;
 use std::path::Path;
-use rand::random;
 use byteorder::{ ReadBytesExt, WriteBytesExt, LittleEndian };
-use super::{ Position, trim_string, string_null_pad, EOR, ElmaError };
+use super::lev::{ Level, ObjectType };
+use super::time::{ Time, EVENT_TIME_SCALE, FRAME_DURATION };
+use super::{ Position, ParseContext, checked_split_at, split_items, trim_string, string_null_pad, EOR, ELMA_REC, ACROSS_REC, ElmaError, RandomSource, ThreadRandom, ZeroRng };
+pub use super::Version;
+
+// Frame flag bits.
+const THROTTLE_FLAG: u8 = 1;
+const RIGHT_FLAG: u8 = 2;
+// Wheel and head offsets are stored in thousandths of a unit.
+const OFFSET_SCALE: f64 = 1000.;
+// Volumes written for new events. Ground touches store the impact strength, so any typical
+// value will do.
+const GROUND_VOLUME: f32 = 0.3104935;
+const EVENT_VOLUME: f32 = 0.99;
+// Stored values for a full turn of the bike and the wheels.
+const BIKE_TURN: f64 = 10000.;
+const WHEEL_TURN: f64 = 250.;
 
 /// One frame of replay.
 #[derive(Debug, Default, PartialEq)]
@@ -16,17 +36,17 @@ pub struct Frame {
     pub right_wheel: Position<i16>,
     /// Head position.
     pub head: Position<i16>,
-    /// Bike rotation. Range 0..10000.
+    /// Bike rotation. Range 0..10000, where 10000 is a full turn.
     pub rotation: i16,
-    /// Left wheel rotation. Range 0..255.
+    /// Left wheel rotation. Range 0..250, where 250 is a full turn.
     pub left_wheel_rotation: u8,
-    /// Right wheel rotation. Range 0..255.
+    /// Right wheel rotation. Range 0..250, where 250 is a full turn.
     pub right_wheel_rotation: u8,
-    /// Throttle.
-    pub throttle: bool,
-    /// Right direction. True = right, False = left.
-    // TODO: consider making right field = direction and enum with right and left?
-    pub right: bool,
+    /// Raw frame flags. Bit 0 is throttle and bit 1 is right direction. Elma stores other
+    /// data in the remaining bits, which is kept as is. There is no brake accessor, since no
+    /// bit follows the brake key: in the test replays bit 2 is set or clear for a whole run,
+    /// bit 3 is always set and bits 4 to 7 look random.
+    pub flags: u8,
     /// Spring sound effect volume.
     pub volume: i16
 }
@@ -47,10 +67,132 @@ impl Frame {
                 rotation: 0,
                 left_wheel_rotation: 0,
                 right_wheel_rotation: 0,
-                throttle: false,
-                right: false,
+                flags: 0,
                 volume: 0 }
     }
+
+    /// Whether throttle is pressed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut frame = elma::rec::Frame::new();
+    /// frame.set_throttle(true);
+    /// assert!(frame.throttle());
+    /// assert_eq!(frame.flags, 1);
+    /// ```
+    pub fn throttle (&self) -> bool {
+        self.flags & THROTTLE_FLAG != 0
+    }
+
+    /// Sets the throttle bit, keeping the other flags.
+    pub fn set_throttle (&mut self, throttle: bool) {
+        self.set_flag(THROTTLE_FLAG, throttle);
+    }
+
+    /// Whether the bike is facing right.
+    pub fn right (&self) -> bool {
+        self.flags & RIGHT_FLAG != 0
+    }
+
+    /// Sets the right direction bit, keeping the other flags.
+    pub fn set_right (&mut self, right: bool) {
+        self.set_flag(RIGHT_FLAG, right);
+    }
+
+    fn set_flag (&mut self, flag: u8, value: bool) {
+        if value { self.flags |= flag } else { self.flags &= !flag }
+    }
+
+    /// Decodes positions and rotations to world coordinates and radians.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let replay = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    /// let state = replay.frames[0].state();
+    /// assert_eq!(state.head.y, f64::from(replay.frames[0].bike.y) + 0.439);
+    /// ```
+    pub fn state (&self) -> FrameState {
+        let bike = Position { x: f64::from(self.bike.x), y: f64::from(self.bike.y) };
+        let offset = |position: &Position<i16>| Position {
+            x: bike.x + f64::from(position.x) / OFFSET_SCALE,
+            y: bike.y + f64::from(position.y) / OFFSET_SCALE
+        };
+        FrameState {
+            left_wheel: offset(&self.left_wheel),
+            right_wheel: offset(&self.right_wheel),
+            head: offset(&self.head),
+            bike,
+            rotation: to_radians(f64::from(self.rotation), BIKE_TURN),
+            left_wheel_rotation: to_radians(f64::from(self.left_wheel_rotation), WHEEL_TURN),
+            right_wheel_rotation: to_radians(f64::from(self.right_wheel_rotation), WHEEL_TURN)
+        }
+    }
+
+    /// Encodes positions and rotations from world coordinates and radians, keeping flags and
+    /// volume. Angles are stored as is when they fit, like wheel rotations 250 to 255 or negative
+    /// bike rotations, so decoded frames encode back unchanged. Other angles are wrapped to one
+    /// turn, and offsets are clamped to what can be stored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let replay = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    /// let mut frame = elma::rec::Frame::new();
+    /// frame.set_state(&replay.frames[100].state());
+    /// frame.flags = replay.frames[100].flags;
+    /// frame.volume = replay.frames[100].volume;
+    /// assert_eq!(frame, replay.frames[100]);
+    /// ```
+    pub fn set_state (&mut self, state: &FrameState) {
+        self.bike = Position { x: state.bike.x as f32, y: state.bike.y as f32 };
+        // Offsets are relative to the stored bike position.
+        let bike = Position { x: f64::from(self.bike.x), y: f64::from(self.bike.y) };
+        let offset = |position: &Position<f64>| Position {
+            x: ((position.x - bike.x) * OFFSET_SCALE).round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16,
+            y: ((position.y - bike.y) * OFFSET_SCALE).round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+        };
+        self.left_wheel = offset(&state.left_wheel);
+        self.right_wheel = offset(&state.right_wheel);
+        self.head = offset(&state.head);
+        let bike_range = f64::from(i16::MIN)..=f64::from(i16::MAX);
+        let wheel_range = 0. ..=f64::from(u8::MAX);
+        self.rotation = from_radians(state.rotation, BIKE_TURN, &bike_range) as i16;
+        self.left_wheel_rotation = from_radians(state.left_wheel_rotation, WHEEL_TURN, &wheel_range) as u8;
+        self.right_wheel_rotation = from_radians(state.right_wheel_rotation, WHEEL_TURN, &wheel_range) as u8;
+    }
+}
+
+/// Decoded replay frame, with positions in world coordinates and rotations in radians.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameState {
+    /// Bike position.
+    pub bike: Position<f64>,
+    /// Left wheel position.
+    pub left_wheel: Position<f64>,
+    /// Right wheel position.
+    pub right_wheel: Position<f64>,
+    /// Head position.
+    pub head: Position<f64>,
+    /// Bike rotation in radians.
+    pub rotation: f64,
+    /// Left wheel rotation in radians.
+    pub left_wheel_rotation: f64,
+    /// Right wheel rotation in radians.
+    pub right_wheel_rotation: f64
+}
+
+/// Converts a stored rotation to radians.
+fn to_radians (value: f64, turn: f64) -> f64 {
+    value / turn * 2. * PI
+}
+
+/// Converts radians to a stored rotation. Values within `range`, the range of the stored
+/// type, are kept as is, and others are wrapped to one turn.
+fn from_radians (angle: f64, turn: f64, range: &RangeInclusive<f64>) -> f64 {
+    let value = (angle / (2. * PI) * turn).round();
+    if range.contains(&value) { value } else { value.rem_euclid(turn) }
 }
 
 
@@ -60,7 +202,12 @@ pub struct Event {
     /// Time of event.
     pub time: f64,
     /// Event type.
-    pub event_type: EventType
+    pub event_type: EventType,
+    /// Sound volume. Ground touches store the strength of the impact, object touches 0 and
+    /// other events 0.99.
+    pub volume: f32,
+    /// Unknown byte after the event type, kept as is.
+    pub unknown: u8
 }
 
 #[derive(Debug, PartialEq)]
@@ -98,11 +245,104 @@ impl Event {
     /// let event = elma::rec::Event::new();
     /// ```
     pub fn new() -> Self {
+        Event::with_type(0_f64, EventType::default())
+    }
+
+    /// Returns a new Event struct of a type, with the volume the game stores for that type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use elma::rec::{ Event, EventType };
+    /// let event = Event::with_type(1.5, EventType::Turn);
+    /// assert_eq!(event.volume, 0.99);
+    /// ```
+    pub fn with_type (time: f64, event_type: EventType) -> Self {
+        let volume = match event_type {
+            EventType::Touch { .. } => 0.,
+            EventType::Ground { alternative: false } => GROUND_VOLUME,
+            _ => EVENT_VOLUME
+        };
         Event {
-            time: 0_f64,
-            event_type: EventType::default()
+            time,
+            event_type,
+            volume,
+            unknown: 0
         }
     }
+
+    /// Frame position of the event: the index of the frame before it, with the fraction of
+    /// the way to the next frame. Unlike `Time`, this is not rounded to milliseconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut event = elma::rec::Event::new();
+    /// event.time = 0.5;
+    /// assert!((event.frame() - 34.341).abs() < 0.001);
+    /// ```
+    pub fn frame (&self) -> f64 {
+        self.time * EVENT_TIME_SCALE / FRAME_DURATION
+    }
+}
+
+/// Player of a replay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Player {
+    /// Player one, or the only player of single-player replays.
+    One,
+    /// Player two of multi-player replays.
+    Two
+}
+
+/// Mismatch between a replay and a level.
+#[derive(Debug, PartialEq)]
+pub enum LevelMismatch {
+    /// Replay was not recorded on this level, with replay and level link numbers.
+    Link(u32, u32),
+    /// Touch event with an index that is not an apple, flower or killer in the level.
+    InvalidTouch {
+        /// Player of the event.
+        player: Player,
+        /// Index of the event.
+        event: usize,
+        /// Object index of the touch.
+        index: i16
+    },
+    /// Replay finished with a different number of apples than the level has, with apples
+    /// taken and apples in level.
+    AppleCount(usize, usize)
+}
+
+impl fmt::Display for LevelMismatch {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LevelMismatch::Link(replay, level) => write!(f, "replay link {} does not match level link {}", replay, level),
+            LevelMismatch::InvalidTouch { player, event, index } => {
+                write!(f, "{:?} event #{} touches invalid object {}", player, event, index)
+            },
+            LevelMismatch::AppleCount(taken, total) => write!(f, "finished with {} of {} apples", taken, total)
+        }
+    }
+}
+
+impl error::Error for LevelMismatch {}
+
+/// Flower touch that finished a replay.
+#[derive(Debug, PartialEq)]
+pub struct Finish {
+    /// Player who touched the flower.
+    pub player: Player,
+    /// Index of the finishing touch event in the player's events.
+    pub event: usize,
+    /// Finish time.
+    pub time: Time,
+    /// Object index of the flower.
+    pub flower: usize,
+    /// Number of different apples taken before the flower touch, by either player.
+    pub apples: usize,
+    /// Whether all apples of the level were taken before the flower touch.
+    pub all_apples: bool
 }
 
 /// Replay struct
@@ -110,7 +350,9 @@ impl Event {
 pub struct Replay {
     /// Raw binary data.
     pub raw: Vec<u8>,
-    /// Whether replay is multi-player or not.
+    /// Elma or Across replay.
+    pub version: Version,
+    /// Whether replay is multi-player or not. Always false for Across replays.
     pub multi: bool,
     /// Whether replay is flag-tag or not.
     pub flag_tag: bool,
@@ -125,7 +367,11 @@ pub struct Replay {
     /// Player two frames.
     pub frames_2: Vec<Frame>,
     /// Player two events.
-    pub events_2: Vec<Event>
+    pub events_2: Vec<Event>,
+    /// Unknown value after the level name in the Elma header of player one, kept as is.
+    pub unknown: u32,
+    /// Unknown value after the level name in the Elma header of player two, kept as is.
+    pub unknown_2: u32
 }
 
 impl Default for Replay {
@@ -141,15 +387,30 @@ impl Replay {
     /// let rec = elma::rec::Replay::new();
     /// ```
     pub fn new() -> Self {
+        Replay::new_with_rng(&mut ThreadRandom)
+    }
+
+    /// Return a new Replay struct, with the link number drawn from `rng`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let rec = elma::rec::Replay::new_with_rng(&mut elma::ZeroRng);
+    /// assert_eq!(rec.link, 0);
+    /// ```
+    pub fn new_with_rng<R: RandomSource> (rng: &mut R) -> Self {
         Replay { raw: vec![],
+                 version: Version::default(),
                  multi: false,
                  flag_tag: false,
-                 link: random::<u32>(),
+                 link: rng.next_u32(),
                  level: String::new(),
                  frames: vec![],
                  events: vec![],
                  frames_2: vec![],
-                 events_2: vec![] }
+                 events_2: vec![],
+                 unknown: 0,
+                 unknown_2: 0 }
     }
 
     /// Loads a replay file and returns a Replay struct.
@@ -160,59 +421,115 @@ impl Replay {
     /// let rec = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
     /// ```
     pub fn load<P: AsRef<Path>> (filename: P) -> Result<Self, ElmaError> {
-        let mut replay = Replay::new();
-        let mut file = File::open(filename)?;
+        Replay::from_reader(File::open(filename)?)
+    }
+
+    /// Reads a replay from any reader, like an upload body or a file inside an archive, and
+    /// returns a Replay struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let file = std::fs::File::open("tests/assets/replays/test_1.rec").unwrap();
+    /// let rec = elma::rec::Replay::from_reader(file).unwrap();
+    /// ```
+    pub fn from_reader<R: Read> (mut reader: R) -> Result<Self, ElmaError> {
         let mut buffer = vec![];
-        file.read_to_end(&mut buffer)?;
-        replay.raw = buffer;
+        reader.read_to_end(&mut buffer)?;
+        Replay::from_bytes(buffer)
+    }
+
+    /// Parses replay data already in memory and returns a Replay struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let rec = elma::rec::Replay::new();
+    /// let mut bytes = vec![];
+    /// rec.write_to(&mut bytes).unwrap();
+    /// assert_eq!(elma::rec::Replay::from_bytes(bytes).unwrap().link, rec.link);
+    /// ```
+    pub fn from_bytes<B: Into<Vec<u8>>> (data: B) -> Result<Self, ElmaError> {
+        // Every random field is parsed, so there is no need to draw from the thread RNG.
+        let mut replay = Replay::new_with_rng(&mut ZeroRng);
+        replay.raw = data.into();
         replay.parse_replay()?;
         Ok(replay)
     }
 
     /// Parses the raw binary data into Replay struct fields.
     fn parse_replay (&mut self) -> Result<(), ElmaError> {
+        let mut context = ParseContext::new();
+        self.parse_replay_data(&mut context).map_err(|error| context.wrap(error))
+    }
+
+    fn parse_replay_data (&mut self, context: &mut ParseContext) -> Result<(), ElmaError> {
+        let total = self.raw.len();
         let mut remaining = self.raw.as_slice();
 
         // Frame count.
-        let frame_count = remaining.read_i32::<LittleEndian>()?;
-        // Some unused value, always 0x83.
-        let (_, mut remaining) = remaining.split_at(4);
-        // Multi-player replay.
-        self.multi = remaining.read_i32::<LittleEndian>()? > 0;
-        // Flag-tag replay.
-        self.flag_tag = remaining.read_i32::<LittleEndian>()? > 0;
+        context.section(0, "player 1 header");
+        let frame_count = parse_count(remaining.read_i32::<LittleEndian>()?)?;
+        // Replay version.
+        self.version = match remaining.read_i32::<LittleEndian>()? {
+            ELMA_REC => Version::Elma,
+            ACROSS_REC => Version::Across,
+            _ => return Err(ElmaError::InvalidReplayFile)
+        };
+        if self.version == Version::Elma {
+            // Multi-player replay.
+            self.multi = remaining.read_i32::<LittleEndian>()? > 0;
+            // Flag-tag replay.
+            self.flag_tag = remaining.read_i32::<LittleEndian>()? > 0;
+        }
         // Level link.
         self.link = remaining.read_u32::<LittleEndian>()?;
         // Level file name, including extension.
-        let (level, remaining) = remaining.split_at(12);
+        let (level, remaining) = checked_split_at(remaining, 12)?;
         self.level = trim_string(level)?;
-        // Unknown, unused.
-        let (_, remaining) = remaining.split_at(4);
+        // Unknown. Not present in Across replays.
+        let mut remaining = remaining;
+        if self.version == Version::Elma {
+            self.unknown = remaining.read_u32::<LittleEndian>()?;
+        }
         // Frames.
-        self.frames = parse_frames(remaining, frame_count)?;
-        let (_, mut remaining) = remaining.split_at(27*frame_count as usize);
+        context.section(total - remaining.len(), "player 1 frames");
+        let frame_size = frame_size(&self.version);
+        let (frame_data, mut remaining) = split_items(remaining, frame_count, frame_size)?;
+        self.frames = parse_frames(frame_data, frame_count, &self.version)?;
         // Events.
-        let event_count = remaining.read_i32::<LittleEndian>()?;
-        self.events = parse_events(remaining, event_count)?;
-        let (_, mut remaining) = remaining.split_at(16*event_count as usize);
+        context.section(total - remaining.len(), "player 1 event count");
+        let event_count = parse_count(remaining.read_i32::<LittleEndian>()?)?;
+        context.section(total - remaining.len(), "player 1 event");
+        let (event_data, mut remaining) = split_items(remaining, event_count, 16)?;
+        self.events = parse_events(event_data, event_count, context)?;
+        // Across replays have no end of replay marker.
+        if self.version == Version::Across { return Ok(()) }
         // End of replay marker.
+        context.section(total - remaining.len(), "player 1 end-of-replay marker");
         let expected = remaining.read_i32::<LittleEndian>()?;
         if expected != EOR { return Err(ElmaError::EORMismatch); }
 
         // If multi-rec, parse frame and events, while skipping other fields?
         if self.multi {
             // Frame count.
-            let frame_count = remaining.read_i32::<LittleEndian>()?;
-            // Skip other fields.
-            let (_, remaining) = remaining.split_at(32);
+            context.section(total - remaining.len(), "player 2 header");
+            let frame_count = parse_count(remaining.read_i32::<LittleEndian>()?)?;
+            // Skip fields repeated from player one, up to the unknown value.
+            let (_, mut remaining) = checked_split_at(remaining, 28)?;
+            self.unknown_2 = remaining.read_u32::<LittleEndian>()?;
             // Frames.
-            self.frames_2 = parse_frames(remaining, frame_count)?;
-            let (_, mut remaining) = remaining.split_at(27*frame_count as usize);
+            context.section(total - remaining.len(), "player 2 frames");
+            let (frame_data, mut remaining) = split_items(remaining, frame_count, frame_size)?;
+            self.frames_2 = parse_frames(frame_data, frame_count, &self.version)?;
             // Events.
-            let event_count = remaining.read_i32::<LittleEndian>()?;
-            self.events_2 = parse_events(remaining, event_count)?;
-            let (_, mut remaining) = remaining.split_at(16*event_count as usize);
+            context.section(total - remaining.len(), "player 2 event count");
+            let event_count = parse_count(remaining.read_i32::<LittleEndian>()?)?;
+            context.section(total - remaining.len(), "player 2 event");
+            let (event_data, mut remaining) = split_items(remaining, event_count, 16)?;
+            self.events_2 = parse_events(event_data, event_count, context)?;
             // End of replay marker.
+            context.section(total - remaining.len(), "player 2 end-of-replay marker");
             let expected = remaining.read_i32::<LittleEndian>()?;
             if expected != EOR { return Err(ElmaError::EORMismatch); }
         }
@@ -228,57 +545,89 @@ impl Replay {
         } else {
             bytes.write_i32::<LittleEndian>(self.frames.len() as i32)?;
         }
-        // Garbage value.
-        bytes.write_i32::<LittleEndian>(0x83_i32)?;
-        // Multi-player replay or not.
-        bytes.write_i32::<LittleEndian>(if self.multi { 1_i32 } else { 0_i32 })?;
-        // Flag-tag replay or not.
-        bytes.write_i32::<LittleEndian>(if self.flag_tag { 1_i32 } else { 0_i32 })?;
+        match self.version {
+            Version::Elma => {
+                // Replay version.
+                bytes.write_i32::<LittleEndian>(ELMA_REC)?;
+                // Multi-player replay or not.
+                bytes.write_i32::<LittleEndian>(if self.multi { 1_i32 } else { 0_i32 })?;
+                // Flag-tag replay or not.
+                bytes.write_i32::<LittleEndian>(if self.flag_tag { 1_i32 } else { 0_i32 })?;
+            },
+            Version::Across => {
+                // Replay version.
+                bytes.write_i32::<LittleEndian>(ACROSS_REC)?;
+            }
+        }
         // Link.
         bytes.write_u32::<LittleEndian>(self.link)?;
         // Level name.
-        bytes.extend_from_slice(&string_null_pad(&self.level, 12)?);
-        // Garbage value.
-        bytes.write_i32::<LittleEndian>(0x00_i32)?;
+        bytes.extend_from_slice(&self.level_name_bytes()?);
+        // Unknown value.
+        if self.version == Version::Elma {
+            bytes.write_u32::<LittleEndian>(if multi { self.unknown_2 } else { self.unknown })?;
+        }
 
         // Frames and events.
         if multi {
-            bytes.extend_from_slice(&write_frames(&self.frames_2)?);
+            bytes.extend_from_slice(&write_frames(&self.frames_2, &self.version)?);
             bytes.extend_from_slice(&write_events(&self.events_2)?);
         } else {
-            bytes.extend_from_slice(&write_frames(&self.frames)?);
+            bytes.extend_from_slice(&write_frames(&self.frames, &self.version)?);
             bytes.extend_from_slice(&write_events(&self.events)?);
         }
 
         // EOR marker.
-        bytes.write_i32::<LittleEndian>(EOR)?;
+        if self.version == Version::Elma {
+            bytes.write_i32::<LittleEndian>(EOR)?;
+        }
 
         Ok(bytes)
     }
 
-    /// Save replay as a file.
+    /// Padded level name. Keeps the bytes of `raw` if the name is unchanged, since the game
+    /// leaves garbage after the terminator.
+    fn level_name_bytes (&self) -> Result<Vec<u8>, ElmaError> {
+        let (version, offset) = match self.version { Version::Elma => (ELMA_REC, 20), Version::Across => (ACROSS_REC, 12) };
+        if let (Some(mut raw_version), Some(name)) = (self.raw.get(4..8), self.raw.get(offset..offset + 12)) {
+            if raw_version.read_i32::<LittleEndian>()? == version && trim_string(name).ok().as_ref() == Some(&self.level) {
+                return Ok(name.to_vec())
+            }
+        }
+        string_null_pad(&self.level, 12)
+    }
+
+    /// Save replay as a file. Multi-player replays give `ElmaError::AcrossMultiPlayer` when
+    /// saved as Across replays.
     pub fn save<P: AsRef<Path>> (&self, filename: P) -> Result<(), ElmaError> {
+        self.write_to(File::create(filename)?)
+    }
+
+    /// Writes the replay to any writer. Multi-player replays give
+    /// `ElmaError::AcrossMultiPlayer` when written as Across replays.
+    pub fn write_to<W: Write> (&self, mut writer: W) -> Result<(), ElmaError> {
+        if self.multi && self.version == Version::Across { return Err(ElmaError::AcrossMultiPlayer) }
         let mut bytes = self.write_rec(false)?;
-        if self.multi {
+        if self.multi && self.version == Version::Elma {
             bytes.extend_from_slice(&self.write_rec(true)?);
         }
-        let mut file = File::create(filename)?;
-        file.write_all(&bytes)?;
+        writer.write_all(&bytes)?;
         Ok(())
     }
 
-    /// Get time of replay. Returns tuple with milliseconds and whether replay was finished,
-    /// caveat being that there is no way to tell if a replay was finished or not just from the
-    /// replay file with a 100% certainty. Merely provided for convinience.
+    /// Get time of replay. Returns tuple with time in milliseconds and whether replay was
+    /// finished, caveat being that there is no way to tell if a replay was finished or not just
+    /// from the replay file with a 100% certainty. Merely provided for convinience. Use `finish`
+    /// to get the exact result with the level.
     /// # Examples
     ///
     /// ```
     /// let replay = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
     /// let (time, finished) = replay.get_time_ms();
-    /// assert_eq!(time, 14649);
+    /// assert_eq!(time.ms(), 14649);
     /// assert_eq!(finished, true);
     /// ```
-    pub fn get_time_ms (&self) -> (usize, bool) {
+    pub fn get_time_ms (&self) -> (Time, bool) {
         // First check if last event was a touch event in either event data.
         let last_event_1 = self.events.last();
         let last_event_2 = self.events_2.last();
@@ -299,100 +648,280 @@ impl Replay {
         };
 
         // Highest frame time.
-        let frames_1_len = self.frames.len();
-        let frames_2_len = self.frames_2.len();
-        let frame_time_max = if frames_1_len > frames_2_len { frames_1_len } else { frames_2_len } as f64 * 33.333;
+        let frame_time_max = Time::from_frames(self.frames.len().max(self.frames_2.len()));
 
         // If neither had a touch event, return approximate frame time.
         if (time_1 == 0.) && (time_2 == 0.) {
-            return (frame_time_max.round() as usize, false);
+            return (frame_time_max, false);
         }
 
         // Set to highest event time.
-        let event_time_max = if time_1 > time_2 { time_1 } else { time_2 } * 2289.37728938;
+        let event_time_max = Time::from_event_time(time_1.max(time_2));
         // If event difference to frame time is >1 frames of time, probably not finished?
-        if frame_time_max > (event_time_max + 33.333) {
-            return (frame_time_max.round() as usize, false);
+        if frame_time_max > event_time_max + Time::from_frames(1) {
+            return (frame_time_max, false);
         }
 
-        (event_time_max.round() as usize, true)
+        (event_time_max, true)
     }
 
-    /// Get time of replay. Returns tuple with hundredths and whether replay was finished,
-    /// caveat being that there is no way to tell if a replay was finished or not just from the
-    /// replay file with a 100% certainty. Merely provided for convinience.
+    /// Get time of replay. Returns tuple with time truncated to hundredths and whether replay
+    /// was finished, caveat being that there is no way to tell if a replay was finished or not
+    /// just from the replay file with a 100% certainty. Merely provided for convinience.
     ///
     /// # Examples
     ///
     /// ```
     /// let replay = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
     /// let (time, finished) = replay.get_time_hs();
-    /// assert_eq!(time, 1464);
+    /// assert_eq!(time.hundredths(), 1464);
+    /// assert_eq!(time.to_string(), "00:14,64");
     /// assert_eq!(finished, true);
     /// ```
-    pub fn get_time_hs (&self) -> (usize, bool) {
+    pub fn get_time_hs (&self) -> (Time, bool) {
         let (time, finished) = self.get_time_ms();
-        (time / 10, finished)
+        (Time::from_ms(time.hundredths() * 10), finished)
     }
+
+    /// Events of a player.
+    pub fn events_of (&self, player: Player) -> &[Event] {
+        match player {
+            Player::One => &self.events,
+            Player::Two => &self.events_2
+        }
+    }
+
+    /// Frames of a player.
+    pub fn frames_of (&self, player: Player) -> &[Frame] {
+        match player {
+            Player::One => &self.frames,
+            Player::Two => &self.frames_2
+        }
+    }
+
+    /// State of a player at a time between the first and last frame, interpolated between the
+    /// two nearest frames. `time` is in event time units like `Event::time`, so events can be
+    /// sampled without rounding; use `Time::event_time` for other times. Rotations take the
+    /// shorter way around, and are wrapped to 0..2π between frames. Returns `None` outside the
+    /// recorded frames.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use elma::rec::{ Replay, Player };
+    /// let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    /// let state = replay.state_at(Player::One, replay.events[0].time).unwrap();
+    /// assert_eq!(replay.state_at(Player::One, 0.), Some(replay.frames[0].state()));
+    /// assert_eq!(replay.state_at(Player::Two, 0.), None);
+    /// ```
+    pub fn state_at (&self, player: Player, time: f64) -> Option<FrameState> {
+        let frames = self.frames_of(player);
+        let position = time * EVENT_TIME_SCALE / FRAME_DURATION;
+        if frames.is_empty() || position < 0. || position > (frames.len() - 1) as f64 {
+            return None
+        }
+        let index = position.floor() as usize;
+        let fraction = position - index as f64;
+        let a = frames[index].state();
+        let b = match frames.get(index + 1) {
+            Some(frame) if fraction > 0. => frame.state(),
+            _ => return Some(a)
+        };
+
+        let lerp = |a: f64, b: f64| a + (b - a) * fraction;
+        let lerp_position = |a: Position<f64>, b: Position<f64>| Position { x: lerp(a.x, b.x), y: lerp(a.y, b.y) };
+        let lerp_angle = |a: f64, b: f64| {
+            let difference = (b - a + PI).rem_euclid(2. * PI) - PI;
+            (a + difference * fraction).rem_euclid(2. * PI)
+        };
+        Some(FrameState {
+            bike: lerp_position(a.bike, b.bike),
+            left_wheel: lerp_position(a.left_wheel, b.left_wheel),
+            right_wheel: lerp_position(a.right_wheel, b.right_wheel),
+            head: lerp_position(a.head, b.head),
+            rotation: lerp_angle(a.rotation, b.rotation),
+            left_wheel_rotation: lerp_angle(a.left_wheel_rotation, b.left_wheel_rotation),
+            right_wheel_rotation: lerp_angle(a.right_wheel_rotation, b.right_wheel_rotation)
+        })
+    }
+
+    /// Checks that the replay was recorded on `level`. Returns all mismatches found, or an empty
+    /// list if the replay matches.
+    ///
+    /// Checks that the links match, that all touch events refer to apples, flowers or killers,
+    /// and that a replay ending with a flower touch took all apples of the level. Apples
+    /// taken by either player of a multi-player replay count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use elma::rec::{ Replay, LevelMismatch };
+    /// let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    /// let level = elma::lev::Level::load("tests/assets/levels/test_1.lev").unwrap();
+    /// assert_eq!(replay.check_level(&level)[0], LevelMismatch::Link(2549082363, 1524269776));
+    /// ```
+    pub fn check_level (&self, level: &Level) -> Vec<LevelMismatch> {
+        let mut mismatches = vec![];
+        if self.link != level.link {
+            mismatches.push(LevelMismatch::Link(self.link, level.link));
+        }
+
+        for &player in &[Player::One, Player::Two] {
+            for (n, event) in self.events_of(player).iter().enumerate() {
+                if let EventType::Touch { index } = event.event_type {
+                    if touched_object(level, index).is_none() {
+                        mismatches.push(LevelMismatch::InvalidTouch { player, event: n, index });
+                    }
+                }
+            }
+        }
+
+        if let Some(finish) = self.finish(level) {
+            if !finish.all_apples {
+                mismatches.push(LevelMismatch::AppleCount(finish.apples, level_apples(level)));
+            }
+        }
+        mismatches
+    }
+
+    /// Returns how the replay finished on `level`, or `None` if it didn't. A replay is finished
+    /// if the last event of a player is a flower touch, since recording stops there. If both
+    /// players touched a flower, the first touch counts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use elma::lev::{ Level, Object, ObjectType };
+    /// let replay = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    /// let mut level = Level::new();
+    /// level.objects = vec![Object::new(), Object::new(), Object::new(), Object::new()];
+    /// level.objects[0].object_type = ObjectType::Player;
+    /// level.objects[3].object_type = ObjectType::Exit;
+    /// let finish = replay.finish(&level).unwrap();
+    /// assert_eq!(finish.time.ms(), 14649);
+    /// assert_eq!(finish.flower, 3);
+    /// assert!(finish.all_apples);
+    /// ```
+    pub fn finish (&self, level: &Level) -> Option<Finish> {
+        let (player, event, time, flower) = [Player::One, Player::Two].iter().filter_map(|&player| {
+            let events = self.events_of(player);
+            let event = events.last()?;
+            match event.event_type {
+                EventType::Touch { index } => match touched_object(level, index) {
+                    Some(&ObjectType::Exit) => Some((player, events.len() - 1, event.time, index as usize)),
+                    _ => None
+                },
+                _ => None
+            }
+        }).min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal))?;
+
+        let mut apples: Vec<_> = [Player::One, Player::Two].iter()
+                                                           .flat_map(|&player| self.events_of(player))
+                                                           .filter(|other| other.time <= time)
+                                                           .filter_map(|other| match other.event_type {
+                                                               EventType::Touch { index } => match touched_object(level, index) {
+                                                                   Some(&ObjectType::Apple { .. }) => Some(index),
+                                                                   _ => None
+                                                               },
+                                                               _ => None
+                                                           })
+                                                           .collect();
+        apples.sort();
+        apples.dedup();
+
+        Some(Finish {
+            player,
+            event,
+            time: Time::from_event_time(time),
+            flower,
+            apples: apples.len(),
+            all_apples: apples.len() == level_apples(level)
+        })
+    }
+}
+
+/// Size of one frame in bytes. Across frames have no volume.
+fn frame_size (version: &Version) -> usize {
+    match *version {
+        Version::Elma => 27,
+        Version::Across => 25
+    }
+}
+
+/// Object type of a touch event index, if it is an apple, flower or killer.
+fn touched_object (level: &Level, index: i16) -> Option<&ObjectType> {
+    if index < 0 { return None }
+    match level.objects.get(index as usize) {
+        Some(object) if object.object_type != ObjectType::Player => Some(&object.object_type),
+        _ => None
+    }
+}
+
+/// Number of apples in level.
+fn level_apples (level: &Level) -> usize {
+    level.objects.iter().filter(|object| matches!(object.object_type, ObjectType::Apple { .. })).count()
 }
 
 /// Function for parsing frame data from either single-player or multi-player replays.
-fn parse_frames (frame_data: &[u8], frame_count: i32) -> Result<Vec<Frame>, ElmaError> {
+fn parse_frames (frame_data: &[u8], frame_count: usize, version: &Version) -> Result<Vec<Frame>, ElmaError> {
     let mut frames: Vec<Frame> = vec![];
 
-    let (mut bike_x, remaining) = frame_data.split_at((frame_count*4) as usize);
-    let (mut bike_y, remaining) = remaining.split_at((frame_count*4) as usize);
-    let (mut left_x, remaining) = remaining.split_at((frame_count*2) as usize);
-    let (mut left_y, remaining) = remaining.split_at((frame_count*2) as usize);
-    let (mut right_x, remaining) = remaining.split_at((frame_count*2) as usize);
-    let (mut right_y, remaining) = remaining.split_at((frame_count*2) as usize);
-    let (mut head_x, remaining) = remaining.split_at((frame_count*2) as usize);
-    let (mut head_y, remaining) = remaining.split_at((frame_count*2) as usize);
-    let (mut rotation, remaining) = remaining.split_at((frame_count*2) as usize);
-    let (mut left_rotation, remaining) = remaining.split_at((frame_count) as usize);
-    let (mut right_rotation, remaining) = remaining.split_at((frame_count) as usize);
-    let (mut data, remaining) = remaining.split_at((frame_count) as usize);
-    let (mut volume, _) = remaining.split_at((frame_count*2) as usize);
+    let (mut bike_x, remaining) = split_items(frame_data, frame_count, 4)?;
+    let (mut bike_y, remaining) = split_items(remaining, frame_count, 4)?;
+    let (mut left_x, remaining) = split_items(remaining, frame_count, 2)?;
+    let (mut left_y, remaining) = split_items(remaining, frame_count, 2)?;
+    let (mut right_x, remaining) = split_items(remaining, frame_count, 2)?;
+    let (mut right_y, remaining) = split_items(remaining, frame_count, 2)?;
+    let (mut head_x, remaining) = split_items(remaining, frame_count, 2)?;
+    let (mut head_y, remaining) = split_items(remaining, frame_count, 2)?;
+    let (mut rotation, remaining) = split_items(remaining, frame_count, 2)?;
+    let (mut left_rotation, remaining) = checked_split_at(remaining, frame_count)?;
+    let (mut right_rotation, remaining) = checked_split_at(remaining, frame_count)?;
+    let (mut data, remaining) = checked_split_at(remaining, frame_count)?;
+    let (mut volume, _) = match *version {
+        Version::Elma => split_items(remaining, frame_count, 2)?,
+        Version::Across => (&[][..], remaining)
+    };
 
     for _ in 0..frame_count {
         // Bike X and Y.
         let x = bike_x.read_f32::<LittleEndian>()?;
         let y = bike_y.read_f32::<LittleEndian>()?;
-        let bike = Position { x: x, y: y };
+        let bike = Position { x, y };
         // Left wheel X and Y.
         let x = left_x.read_i16::<LittleEndian>()?;
         let y = left_y.read_i16::<LittleEndian>()?;
-        let left_wheel = Position { x: x, y: y };
+        let left_wheel = Position { x, y };
         // Right wheel X and Y.
         let x = right_x.read_i16::<LittleEndian>()?;
         let y = right_y.read_i16::<LittleEndian>()?;
-        let right_wheel = Position { x: x, y: y };
+        let right_wheel = Position { x, y };
         // Head X and Y.
         let x = head_x.read_i16::<LittleEndian>()?;
         let y = head_y.read_i16::<LittleEndian>()?;
-        let head = Position { x: x, y: y };
+        let head = Position { x, y };
         // Rotations.
         let rotation = rotation.read_i16::<LittleEndian>()?;
         let left_wheel_rotation = left_rotation.read_u8()?;
         let right_wheel_rotation = right_rotation.read_u8()?;
-        // Throttle and turn right.
-        let data = data.read_u8()?;
-        let throttle = data & 1 != 0;
-        let right = data & (1 << 1) != 0;
+        // Throttle, turn right and other flags.
+        let flags = data.read_u8()?;
         // Sound effect volume.
-        let volume = volume.read_i16::<LittleEndian>()?;
+        let volume = match *version {
+            Version::Elma => volume.read_i16::<LittleEndian>()?,
+            Version::Across => 0
+        };
 
         frames.push(Frame {
-            bike: bike,
-            left_wheel: left_wheel,
-            right_wheel: right_wheel,
-            head: head,
-            rotation: rotation,
-            left_wheel_rotation: left_wheel_rotation,
-            right_wheel_rotation: right_wheel_rotation,
-            throttle: throttle,
-            right: right,
-            volume: volume
+            bike,
+            left_wheel,
+            right_wheel,
+            head,
+            rotation,
+            left_wheel_rotation,
+            right_wheel_rotation,
+            flags,
+            volume
         });
     }
 
@@ -400,18 +929,20 @@ fn parse_frames (frame_data: &[u8], frame_count: i32) -> Result<Vec<Frame>, Elma
 }
 
 /// Function for parsing event data from either single-player or multi-player replays.
-fn parse_events (mut event_data: &[u8], event_count: i32) -> Result<Vec<Event>, ElmaError> {
+fn parse_events (mut event_data: &[u8], event_count: usize, context: &mut ParseContext) -> Result<Vec<Event>, ElmaError> {
+    let (start, section) = (context.offset, context.section);
     let mut events: Vec<Event> = vec![];
 
-    for _ in 0..event_count {
+    for index in 0..event_count {
+        context.item(start + index * 16, section, index);
         // Event time
         let time = event_data.read_f64::<LittleEndian>()?;
         // Event details
         let info = event_data.read_i16::<LittleEndian>()?;
         let event = event_data.read_u8()?;
-        // Unknown values
-        let _ = event_data.read_u8()?;
-        let _ = event_data.read_f32::<LittleEndian>()?;
+        // Unknown value and sound volume.
+        let unknown = event_data.read_u8()?;
+        let volume = event_data.read_f32::<LittleEndian>()?;
         let event_type = match event {
             0 => EventType::Touch { index: info },
             1 => EventType::Ground { alternative: false },
@@ -423,16 +954,24 @@ fn parse_events (mut event_data: &[u8], event_count: i32) -> Result<Vec<Event>,
         };
 
         events.push(Event {
-            time: time,
-            event_type: event_type
+            time,
+            event_type,
+            volume,
+            unknown
         });
     }
 
     Ok(events)
 }
 
+/// Converts a frame or event count read from a replay file, rejecting negative counts.
+fn parse_count (count: i32) -> Result<usize, ElmaError> {
+    if count < 0 { return Err(ElmaError::InvalidReplayFile) }
+    Ok(count as usize)
+}
+
 /// Function for writing frame data.
-fn write_frames (frame_data: &[Frame]) -> Result<Vec<u8>, ElmaError> {
+fn write_frames (frame_data: &[Frame], version: &Version) -> Result<Vec<u8>, ElmaError> {
     let mut bytes = vec![];
 
     let mut bike_x = vec![];
@@ -466,10 +1005,7 @@ fn write_frames (frame_data: &[Frame]) -> Result<Vec<u8>, ElmaError> {
         left_rotation.write_u8(frame.left_wheel_rotation)?;
         right_rotation.write_u8(frame.right_wheel_rotation)?;
 
-        let mut data_temp = random::<u8>() & 0xFC;
-        if frame.throttle { data_temp |= 1; }
-        if frame.right { data_temp |= 2; }
-        data.write_u8(data_temp)?;
+        data.write_u8(frame.flags)?;
 
         volume.write_i16::<LittleEndian>(frame.volume)?;
     }
@@ -486,7 +1022,9 @@ fn write_frames (frame_data: &[Frame]) -> Result<Vec<u8>, ElmaError> {
     bytes.extend_from_slice(&left_rotation);
     bytes.extend_from_slice(&right_rotation);
     bytes.extend_from_slice(&data);
-    bytes.extend_from_slice(&volume);
+    if *version == Version::Elma {
+        bytes.extend_from_slice(&volume);
+    }
 
     Ok(bytes)
 }
@@ -500,21 +1038,19 @@ fn write_events (event_data: &[Event]) -> Result<Vec<u8>, ElmaError> {
 
     for event in event_data {
         bytes.write_f64::<LittleEndian>(event.time)?;
-        match event.event_type {
-            EventType::Touch { index: info } => { bytes.write_u32::<LittleEndian>(info as u32)?;
-                                                  bytes.write_u32::<LittleEndian>(0 as u32)?; },
-            EventType::Ground { alternative: false } => { bytes.write_u32::<LittleEndian>(131071 as u32)?;
-                                                          bytes.write_u32::<LittleEndian>(1050605825 as u32)?; },
-            EventType::Ground { alternative: true } => { bytes.write_u32::<LittleEndian>(327679 as u32)?;
-                                                          bytes.write_u32::<LittleEndian>(1065185444 as u32)?; },
-            EventType::Turn => { bytes.write_u32::<LittleEndian>(393215 as u32)?;
-                                 bytes.write_u32::<LittleEndian>(1065185444 as u32)?; },
-            EventType::VoltRight => { bytes.write_u32::<LittleEndian>(458751 as u32)?;
-                                      bytes.write_u32::<LittleEndian>(1065185444 as u32)?; },
-            EventType::VoltLeft => { bytes.write_u32::<LittleEndian>(524287 as u32)?;
-                                      bytes.write_u32::<LittleEndian>(1065185444 as u32)?; }
-        }
-
+        // Object index, -1 for other events, and event type.
+        let (info, event_type) = match event.event_type {
+            EventType::Touch { index } => (index, 0),
+            EventType::Ground { alternative: false } => (-1, 1),
+            EventType::Ground { alternative: true } => (-1, 4),
+            EventType::Turn => (-1, 5),
+            EventType::VoltRight => (-1, 6),
+            EventType::VoltLeft => (-1, 7)
+        };
+        bytes.write_i16::<LittleEndian>(info)?;
+        bytes.write_u8(event_type)?;
+        bytes.write_u8(event.unknown)?;
+        bytes.write_f32::<LittleEndian>(event.volume)?;
     }
 
     Ok(bytes)
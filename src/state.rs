@@ -0,0 +1,493 @@
+use std::io::{ Read, Write };
+use std::fs::File;
+use std::path::Path;
+use byteorder::{ ReadBytesExt, WriteBytesExt, LittleEndian };
+use super::{ checked_split_at, split_items, trim_string, string_null_pad, ElmaError };
+use super::lev::{ ListEntry, parse_top10, write_top10 };
+
+// state.dat version number.
+const STATE_VERSION: i32 = 200;
+// Magic arbitrary number signifying end-of-file in state.dat.
+const STATE_EOF: i32 = 123432221;
+// Number of internal levels with top10 lists.
+const INTERNALS: usize = 90;
+// Number of player slots.
+const PLAYERS: usize = 50;
+// Size of one player slot.
+const PLAYER_SIZE: usize = 116;
+// Sizes of the separately encrypted fields, in file order. Everything except the end-of-file
+// marker is encrypted, with the key restarting for each field.
+const STATE_FIELDS: [usize; 35] = [
+    // Version, top10 lists, players and player count.
+    4, INTERNALS * 688, PLAYERS * PLAYER_SIZE, 4,
+    // Player A and B names.
+    15, 15,
+    // Sound, sound optimization, play mode, flag tag, swap bikes, video detail, animated
+    // objects and animated menus.
+    4, 4, 4, 4, 4, 4, 4, 4,
+    // Player A keys.
+    4, 4, 4, 4, 4, 4, 4, 4,
+    // Player B keys.
+    4, 4, 4, 4, 4, 4, 4, 4,
+    // Screen size and screenshot keys.
+    4, 4, 4,
+    // Last edited and last played external level names.
+    20, 20];
+
+/// Top10 lists of one internal level.
+#[derive(Debug, Default, PartialEq)]
+pub struct BestTimes {
+    /// Single-player names and times.
+    pub single: Vec<ListEntry>,
+    /// Multi-player names and times.
+    pub multi: Vec<ListEntry>
+}
+
+/// Player entry.
+#[derive(Debug, Default, PartialEq)]
+pub struct PlayerEntry {
+    /// Player name.
+    pub name: String,
+    /// Whether each internal level has been skipped.
+    pub skipped: Vec<bool>,
+    /// Number of internal levels reached.
+    pub levels_reached: i32,
+    /// Currently selected internal level.
+    pub selected_level: i32
+}
+
+impl PlayerEntry {
+    /// Creates a new player entry with no levels reached.
+    pub fn new (name: &str) -> Self {
+        PlayerEntry {
+            name: String::from(name),
+            skipped: vec![false; INTERNALS],
+            levels_reached: 1,
+            selected_level: 0
+        }
+    }
+}
+
+/// Key bindings of one player, as keyboard scan codes.
+#[derive(Debug, Default, PartialEq)]
+pub struct PlayerKeys {
+    /// Throttle.
+    pub throttle: u32,
+    /// Brake.
+    pub brake: u32,
+    /// Rotate (volt) right.
+    pub rotate_right: u32,
+    /// Rotate (volt) left.
+    pub rotate_left: u32,
+    /// Change direction.
+    pub change_direction: u32,
+    /// Toggle navigator.
+    pub toggle_navigator: u32,
+    /// Toggle timer.
+    pub toggle_timer: u32,
+    /// Toggle show/hide.
+    pub toggle_show_hide: u32
+}
+
+/// Sound optimization setting.
+#[derive(Debug, Default, PartialEq)]
+pub enum SoundOptimization {
+    /// Compatibility mode.
+    Compatibility,
+    /// Best quality mode.
+    #[default]
+    BestQuality
+}
+
+/// Play mode setting.
+#[derive(Debug, Default, PartialEq)]
+pub enum PlayMode {
+    /// Single-player.
+    #[default]
+    Single,
+    /// Multi-player.
+    Multi
+}
+
+/// Video detail setting.
+#[derive(Debug, Default, PartialEq)]
+pub enum VideoDetail {
+    /// Low detail.
+    Low,
+    /// High detail.
+    #[default]
+    High
+}
+
+/// State struct that contains all state.dat information.
+#[derive(Debug, PartialEq)]
+pub struct State {
+    /// Raw binary data of a loaded or saved state.dat, decrypted.
+    pub raw: Vec<u8>,
+    /// Top10 lists of each internal level.
+    pub times: Vec<BestTimes>,
+    /// Player entries. At most 50 players are saved.
+    pub players: Vec<PlayerEntry>,
+    /// Last selected player A name.
+    pub player_a_name: String,
+    /// Last selected player B name.
+    pub player_b_name: String,
+    /// Sound enabled.
+    pub sound: bool,
+    /// Sound optimization.
+    pub sound_optimization: SoundOptimization,
+    /// Play mode.
+    pub play_mode: PlayMode,
+    /// Flag tag.
+    pub flag_tag: bool,
+    /// Swap bikes between players.
+    pub swap_bikes: bool,
+    /// Video detail.
+    pub video_detail: VideoDetail,
+    /// Animated objects.
+    pub animated_objects: bool,
+    /// Animated menus.
+    pub animated_menus: bool,
+    /// Player A keys.
+    pub player_a_keys: PlayerKeys,
+    /// Player B keys.
+    pub player_b_keys: PlayerKeys,
+    /// Increase screen size key.
+    pub increase_screen_size_key: u32,
+    /// Decrease screen size key.
+    pub decrease_screen_size_key: u32,
+    /// Screenshot key.
+    pub screenshot_key: u32,
+    /// Last edited level file name.
+    pub last_edited_level: String,
+    /// Last played external level file name.
+    pub last_played_external: String
+}
+
+impl Default for State {
+    fn default() -> State { State::new() }
+}
+
+impl State {
+    /// Returns a new `State` struct with no players or times.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let state = elma::state::State::new();
+    /// ```
+    pub fn new () -> Self {
+        let mut times = vec![];
+        for _ in 0..INTERNALS {
+            times.push(BestTimes::default());
+        }
+
+        State {
+            raw: vec![],
+            times,
+            players: vec![],
+            player_a_name: String::new(),
+            player_b_name: String::new(),
+            sound: true,
+            sound_optimization: SoundOptimization::default(),
+            play_mode: PlayMode::default(),
+            flag_tag: false,
+            swap_bikes: false,
+            video_detail: VideoDetail::default(),
+            animated_objects: true,
+            animated_menus: true,
+            player_a_keys: PlayerKeys::default(),
+            player_b_keys: PlayerKeys::default(),
+            increase_screen_size_key: 0,
+            decrease_screen_size_key: 0,
+            screenshot_key: 0,
+            last_edited_level: String::new(),
+            last_played_external: String::new()
+        }
+    }
+
+    /// Loads a state.dat file and returns a `State` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// let state = elma::state::State::load("state.dat").unwrap();
+    /// ```
+    pub fn load<P: AsRef<Path>> (filename: P) -> Result<Self, ElmaError> {
+        State::from_reader(File::open(filename)?)
+    }
+
+    /// Reads an encrypted state.dat from any reader and returns a `State` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let file = std::fs::File::open("tests/assets/state/constructed.dat").unwrap();
+    /// let state = elma::state::State::from_reader(file).unwrap();
+    /// ```
+    pub fn from_reader<R: Read> (mut reader: R) -> Result<Self, ElmaError> {
+        let mut buffer = vec![];
+        reader.read_to_end(&mut buffer)?;
+        State::from_bytes(buffer)
+    }
+
+    /// Decrypts and parses state.dat data already in memory, and returns a `State` struct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = elma::state::State::new();
+    /// state.players.push(elma::state::PlayerEntry::new("Rust"));
+    /// let mut bytes = vec![];
+    /// state.write_to(&mut bytes).unwrap();
+    /// let parsed = elma::state::State::from_bytes(bytes).unwrap();
+    /// assert_eq!(parsed.players, state.players);
+    /// ```
+    pub fn from_bytes<B: Into<Vec<u8>>> (data: B) -> Result<Self, ElmaError> {
+        let mut state = State::new();
+        state.raw = data.into();
+        crypt_state(&mut state.raw);
+        state.parse_state()?;
+        Ok(state)
+    }
+
+    /// Parses the decrypted raw binary data into `State` struct fields.
+    fn parse_state (&mut self) -> Result<(), ElmaError> {
+        let mut remaining = self.raw.as_slice();
+
+        // Version.
+        if remaining.read_i32::<LittleEndian>()? != STATE_VERSION {
+            return Err(ElmaError::InvalidStateFile)
+        }
+
+        // Top10 lists, single-player list first.
+        let (top10, remaining) = split_items(remaining, INTERNALS, 688)?;
+        self.times = vec![];
+        for lists in top10.chunks(688) {
+            self.times.push(BestTimes {
+                single: parse_top10(&lists[0..344])?,
+                multi: parse_top10(&lists[344..688])?
+            });
+        }
+
+        // Players.
+        let (players, mut remaining) = split_items(remaining, PLAYERS, PLAYER_SIZE)?;
+        let player_count = remaining.read_i32::<LittleEndian>()?;
+        self.players = vec![];
+        for mut player in players.chunks(PLAYER_SIZE).take(player_count as usize) {
+            let name = trim_string(&player[0..15])?;
+            let skipped = player[15..15 + INTERNALS].iter().map(|&skip| skip != 0).collect();
+            // Three bytes of padding.
+            player = &player[108..];
+            let levels_reached = player.read_i32::<LittleEndian>()?;
+            let selected_level = player.read_i32::<LittleEndian>()?;
+            self.players.push(PlayerEntry {
+                name,
+                skipped,
+                levels_reached,
+                selected_level
+            });
+        }
+
+        // Selected player names.
+        let (name, remaining) = checked_split_at(remaining, 15)?;
+        self.player_a_name = trim_string(name)?;
+        let (name, mut remaining) = checked_split_at(remaining, 15)?;
+        self.player_b_name = trim_string(name)?;
+
+        // Settings.
+        self.sound = remaining.read_i32::<LittleEndian>()? != 0;
+        self.sound_optimization = match remaining.read_i32::<LittleEndian>()? {
+            1 => SoundOptimization::Compatibility,
+            _ => SoundOptimization::BestQuality
+        };
+        self.play_mode = match remaining.read_i32::<LittleEndian>()? {
+            1 => PlayMode::Single,
+            _ => PlayMode::Multi
+        };
+        self.flag_tag = remaining.read_i32::<LittleEndian>()? != 0;
+        // Stored inverted.
+        self.swap_bikes = remaining.read_i32::<LittleEndian>()? == 0;
+        self.video_detail = match remaining.read_i32::<LittleEndian>()? {
+            1 => VideoDetail::High,
+            _ => VideoDetail::Low
+        };
+        self.animated_objects = remaining.read_i32::<LittleEndian>()? != 0;
+        self.animated_menus = remaining.read_i32::<LittleEndian>()? != 0;
+
+        // Keys.
+        self.player_a_keys = parse_keys(&mut remaining)?;
+        self.player_b_keys = parse_keys(&mut remaining)?;
+        self.increase_screen_size_key = remaining.read_u32::<LittleEndian>()?;
+        self.decrease_screen_size_key = remaining.read_u32::<LittleEndian>()?;
+        self.screenshot_key = remaining.read_u32::<LittleEndian>()?;
+
+        // Last used level names.
+        let (name, remaining) = checked_split_at(remaining, 20)?;
+        self.last_edited_level = trim_string(name)?;
+        let (name, mut remaining) = checked_split_at(remaining, 20)?;
+        self.last_played_external = trim_string(name)?;
+
+        // EOF marker expected at this point.
+        let expected = remaining.read_i32::<LittleEndian>()?;
+        if expected != STATE_EOF { return Err(ElmaError::EOFMismatch) }
+
+        Ok(())
+    }
+
+    /// Combines the `State` struct fields to generate the decrypted raw binary data.
+    /// Called automatically when using the `save` or `write_to` methods.
+    pub fn update (&mut self) -> Result<(), ElmaError> {
+        let mut bytes = vec![];
+
+        // Version.
+        bytes.write_i32::<LittleEndian>(STATE_VERSION)?;
+
+        // Top10 lists, padded with empty lists for missing levels.
+        for n in 0..INTERNALS {
+            match self.times.get(n) {
+                Some(lists) => {
+                    bytes.extend_from_slice(&write_top10(&lists.single)?);
+                    bytes.extend_from_slice(&write_top10(&lists.multi)?);
+                },
+                None => {
+                    bytes.extend_from_slice(&write_top10(&[])?);
+                    bytes.extend_from_slice(&write_top10(&[])?);
+                }
+            }
+        }
+
+        // Players.
+        let player_count = if PLAYERS < self.players.len() { PLAYERS } else { self.players.len() };
+        for player in self.players.iter().take(PLAYERS) {
+            bytes.extend_from_slice(&string_null_pad(&player.name, 15)?);
+            for n in 0..INTERNALS {
+                bytes.write_u8(if player.skipped.get(n) == Some(&true) { 1 } else { 0 })?;
+            }
+            bytes.extend_from_slice(&[0u8; 3]);
+            bytes.write_i32::<LittleEndian>(player.levels_reached)?;
+            bytes.write_i32::<LittleEndian>(player.selected_level)?;
+        }
+        for _ in player_count..PLAYERS {
+            bytes.extend_from_slice(&[0u8; PLAYER_SIZE]);
+        }
+        bytes.write_i32::<LittleEndian>(player_count as i32)?;
+
+        // Selected player names.
+        bytes.extend_from_slice(&string_null_pad(&self.player_a_name, 15)?);
+        bytes.extend_from_slice(&string_null_pad(&self.player_b_name, 15)?);
+
+        // Settings.
+        bytes.write_i32::<LittleEndian>(if self.sound { 1 } else { 0 })?;
+        bytes.write_i32::<LittleEndian>(match self.sound_optimization {
+            SoundOptimization::Compatibility => 1,
+            SoundOptimization::BestQuality => 0
+        })?;
+        bytes.write_i32::<LittleEndian>(match self.play_mode {
+            PlayMode::Single => 1,
+            PlayMode::Multi => 0
+        })?;
+        bytes.write_i32::<LittleEndian>(if self.flag_tag { 1 } else { 0 })?;
+        bytes.write_i32::<LittleEndian>(if self.swap_bikes { 0 } else { 1 })?;
+        bytes.write_i32::<LittleEndian>(match self.video_detail {
+            VideoDetail::High => 1,
+            VideoDetail::Low => 0
+        })?;
+        bytes.write_i32::<LittleEndian>(if self.animated_objects { 1 } else { 0 })?;
+        bytes.write_i32::<LittleEndian>(if self.animated_menus { 1 } else { 0 })?;
+
+        // Keys.
+        write_keys(&mut bytes, &self.player_a_keys)?;
+        write_keys(&mut bytes, &self.player_b_keys)?;
+        bytes.write_u32::<LittleEndian>(self.increase_screen_size_key)?;
+        bytes.write_u32::<LittleEndian>(self.decrease_screen_size_key)?;
+        bytes.write_u32::<LittleEndian>(self.screenshot_key)?;
+
+        // Last used level names.
+        bytes.extend_from_slice(&string_null_pad(&self.last_edited_level, 20)?);
+        bytes.extend_from_slice(&string_null_pad(&self.last_played_external, 20)?);
+
+        // EOF marker.
+        bytes.write_i32::<LittleEndian>(STATE_EOF)?;
+
+        self.raw = bytes;
+        Ok(())
+    }
+
+    /// Saves state as an encrypted state.dat file.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// let mut state = elma::state::State::new();
+    /// state.players.push(elma::state::PlayerEntry::new("Rust"));
+    /// state.save("state.dat").unwrap();
+    /// ```
+    pub fn save<P: AsRef<Path>> (&mut self, filename: P) -> Result<(), ElmaError> {
+        self.write_to(File::create(filename)?)
+    }
+
+    /// Writes the state to any writer, encrypted like a state.dat file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = elma::state::State::new();
+    /// let mut buffer = vec![];
+    /// state.write_to(&mut buffer).unwrap();
+    /// assert_eq!(buffer.len(), state.raw.len());
+    /// assert_ne!(buffer, state.raw);
+    /// ```
+    pub fn write_to<W: Write> (&mut self, mut writer: W) -> Result<(), ElmaError> {
+        self.update()?;
+        let mut bytes = self.raw.clone();
+        crypt_state(&mut bytes);
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+/// Decrypt and encrypt state.dat data in place. Same algorithm for both.
+pub fn crypt_state (state_data: &mut [u8]) {
+    let mut start = 0;
+    for size in STATE_FIELDS.iter() {
+        let end = start + size;
+        if end > state_data.len() { break }
+
+        let mut ebp8: i16 = 0x17;
+        let mut ebp10: i16 = 0x2636;
+
+        for t in state_data[start..end].iter_mut() {
+            *t ^= (ebp8 & 0xFF) as u8;
+            ebp10 = ebp10.wrapping_add((ebp8.wrapping_rem(0xD3F)).wrapping_mul(0xD3F));
+            ebp8 = ebp10.wrapping_mul(0x1F).wrapping_add(0xD3F);
+        }
+
+        start = end;
+    }
+}
+
+fn parse_keys (buffer: &mut &[u8]) -> Result<PlayerKeys, ElmaError> {
+    Ok(PlayerKeys {
+        throttle: buffer.read_u32::<LittleEndian>()?,
+        brake: buffer.read_u32::<LittleEndian>()?,
+        rotate_right: buffer.read_u32::<LittleEndian>()?,
+        rotate_left: buffer.read_u32::<LittleEndian>()?,
+        change_direction: buffer.read_u32::<LittleEndian>()?,
+        toggle_navigator: buffer.read_u32::<LittleEndian>()?,
+        toggle_timer: buffer.read_u32::<LittleEndian>()?,
+        toggle_show_hide: buffer.read_u32::<LittleEndian>()?
+    })
+}
+
+fn write_keys (bytes: &mut Vec<u8>, keys: &PlayerKeys) -> Result<(), ElmaError> {
+    bytes.write_u32::<LittleEndian>(keys.throttle)?;
+    bytes.write_u32::<LittleEndian>(keys.brake)?;
+    bytes.write_u32::<LittleEndian>(keys.rotate_right)?;
+    bytes.write_u32::<LittleEndian>(keys.rotate_left)?;
+    bytes.write_u32::<LittleEndian>(keys.change_direction)?;
+    bytes.write_u32::<LittleEndian>(keys.toggle_navigator)?;
+    bytes.write_u32::<LittleEndian>(keys.toggle_timer)?;
+    bytes.write_u32::<LittleEndian>(keys.toggle_show_hide)?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::ops::{ Add, AddAssign, Sub, SubAssign };
+use std::str::FromStr;
+use super::ElmaError;
+
+/// Milliseconds per replay frame, recorded at 30 frames per second.
+pub const FRAME_DURATION: f64 = 33.333;
+/// Milliseconds per game time unit, as used in replay event times.
+pub const EVENT_TIME_SCALE: f64 = 2289.37728938;
+// Highest time shown by the game, 59:59,99.
+const MAX_DISPLAY: i64 = 3_599_990;
+
+/// Time in milliseconds, with conversions between the units used in Elma files. Milliseconds
+/// are kept in an `i64` so that any `i32` stored in a file converts without overflow.
+///
+/// # Examples
+///
+/// ```
+/// use elma::time::Time;
+/// let time: Time = "01:02,34".parse().unwrap();
+/// assert_eq!(time, Time::from_hundredths(6234));
+/// assert_eq!(time.to_string(), "01:02,34");
+/// assert!(time < Time::from_ms(62_350));
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Time(pub i64);
+
+impl Time {
+    /// Time from milliseconds.
+    pub fn from_ms (ms: i64) -> Self {
+        Time(ms)
+    }
+
+    /// Time from hundredths, as stored in top10 lists.
+    pub fn from_hundredths (hundredths: i32) -> Self {
+        Time(i64::from(hundredths) * 10)
+    }
+
+    /// Time of a number of replay frames, rounded to milliseconds.
+    pub fn from_frames (frames: usize) -> Self {
+        Time((frames as f64 * FRAME_DURATION).round() as i64)
+    }
+
+    /// Time from a replay event time, rounded to milliseconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let replay = elma::rec::Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    /// let time = elma::time::Time::from_event_time(replay.events.last().unwrap().time);
+    /// assert_eq!(time.ms(), 14649);
+    /// ```
+    pub fn from_event_time (time: f64) -> Self {
+        Time((time * EVENT_TIME_SCALE).round() as i64)
+    }
+
+    /// Milliseconds.
+    pub fn ms (self) -> i64 {
+        self.0
+    }
+
+    /// Whole hundredths, rounded down.
+    pub fn hundredths (self) -> i64 {
+        self.0 / 10
+    }
+
+    /// Number of whole replay frames.
+    pub fn frames (self) -> usize {
+        self.exact_frames().max(0.) as usize
+    }
+
+    /// Number of replay frames, with the fraction of the next frame.
+    pub fn exact_frames (self) -> f64 {
+        self.0 as f64 / FRAME_DURATION
+    }
+
+    /// Replay event time.
+    pub fn event_time (self) -> f64 {
+        self.0 as f64 / EVENT_TIME_SCALE
+    }
+}
+
+impl fmt::Display for Time {
+    /// Formats as "mm:ss,hh" like the game. Times over 59:59,99 are shown as 59:59,99, and
+    /// negative times as 00:00,00.
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hundredths = self.0.clamp(0, MAX_DISPLAY) / 10;
+        write!(f, "{:02}:{:02},{:02}", hundredths / 6000, hundredths / 100 % 60, hundredths % 100)
+    }
+}
+
+impl FromStr for Time {
+    type Err = ElmaError;
+
+    /// Parses "mm:ss,hh" times. Seconds must be below 60.
+    fn from_str (time: &str) -> Result<Self, ElmaError> {
+        let (minutes, rest) = time.split_once(':').ok_or(ElmaError::InvalidTimeFormat)?;
+        let (seconds, hundredths) = rest.split_once(',').ok_or(ElmaError::InvalidTimeFormat)?;
+        let number = |digits: &str, max: i32| -> Result<i32, ElmaError> {
+            if digits.len() != 2 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(ElmaError::InvalidTimeFormat)
+            }
+            let value = digits.parse().map_err(|_| ElmaError::InvalidTimeFormat)?;
+            if value > max { return Err(ElmaError::InvalidTimeFormat) }
+            Ok(value)
+        };
+        Ok(Time::from_hundredths(number(minutes, 59)? * 6000 + number(seconds, 59)? * 100 + number(hundredths, 99)?))
+    }
+}
+
+impl Add for Time {
+    type Output = Time;
+
+    fn add (self, other: Time) -> Time {
+        Time(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Time {
+    fn add_assign (&mut self, other: Time) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Time {
+    type Output = Time;
+
+    fn sub (self, other: Time) -> Time {
+        Time(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Time {
+    fn sub_assign (&mut self, other: Time) {
+        self.0 -= other.0;
+    }
+}
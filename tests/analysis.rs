@@ -0,0 +1,175 @@
+extern crate elma;
+
+use elma::Position;
+use elma::analysis::*;
+use elma::lev::{ Direction, Level, Object, ObjectType };
+use elma::rec::*;
+use elma::time::{ Time, EVENT_TIME_SCALE, FRAME_DURATION };
+
+/// Replay with the bike moving right one unit per frame, and ground touches at `touches` ms.
+fn moving_replay (frames: usize, touches: &[i64]) -> Replay {
+    let mut replay = Replay::new();
+    for n in 0..frames {
+        let mut frame = Frame::new();
+        frame.bike = Position { x: n as f32, y: 0. };
+        replay.frames.push(frame);
+    }
+    replay.events = touches.iter().map(|&ms| Event::with_type(Time::from_ms(ms).event_time(), EventType::Ground { alternative: false })).collect();
+    replay
+}
+
+#[test]
+fn kinematics_constant_speed () {
+    let replay = moving_replay(31, &[300, 600, 900]);
+    let kinematics = kinematics(&replay, Player::One, Time::from_ms(500));
+    assert_eq!(kinematics.speed.len(), 30);
+    assert!((kinematics.speed[0] - 30.).abs() < 0.001);
+    assert!((kinematics.velocity[29].x - 30.).abs() < 0.001);
+    assert_eq!(kinematics.velocity[29].y, 0.);
+    // No acceleration from rest at the start, since the first velocity isn't known.
+    assert_eq!(kinematics.acceleration.len(), 29);
+    assert!(kinematics.acceleration.iter().all(|acceleration| acceleration.x.abs() < 0.001 && acceleration.y == 0.));
+    assert!((kinematics.distance - 30.).abs() < 1e-9);
+    assert!((kinematics.top_speed - 30.).abs() < 0.001);
+    // 30 units in the recorded time of 31 frames.
+    assert!((kinematics.average_speed - 30. / 1.033).abs() < 0.001);
+    assert_eq!(kinematics.air, vec![]);
+    assert_eq!(kinematics.ground_time, Time::from_frames(31));
+}
+
+#[test]
+fn kinematics_air_time () {
+    let replay = moving_replay(31, &[100, 200, 800, 900, 1500]);
+    let kinematics = kinematics(&replay, Player::One, Time::from_ms(500));
+    assert_eq!(kinematics.air, vec![(Time::from_ms(200), Time::from_ms(800)), (Time::from_ms(900), Time::from_frames(31))]);
+    assert_eq!(kinematics.air_time, Time::from_ms(733));
+    assert_eq!(kinematics.ground_time, Time::from_ms(300));
+}
+
+#[test]
+/// Runs starting or ending in the air.
+fn kinematics_air_time_at_start_and_end () {
+    let replay = moving_replay(31, &[600, 700]);
+    let jumps = kinematics(&replay, Player::One, Time::from_ms(300));
+    assert_eq!(jumps.air, vec![(Time::from_ms(0), Time::from_ms(600)), (Time::from_ms(700), Time::from_frames(31))]);
+    assert_eq!(jumps.ground_time, Time::from_ms(100));
+    let no_touches = kinematics(&moving_replay(31, &[]), Player::One, DEFAULT_AIR_GAP);
+    assert_eq!(no_touches.air, vec![(Time::from_ms(0), Time::from_frames(31))]);
+    let short = kinematics(&moving_replay(20, &[]), Player::One, DEFAULT_AIR_GAP);
+    assert_eq!(short.air, vec![]);
+}
+
+#[test]
+fn kinematics_players () {
+    let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    for &player in [Player::One, Player::Two].iter() {
+        let kinematics = kinematics(&replay, player, DEFAULT_AIR_GAP);
+        assert_eq!(kinematics.velocity.len(), replay.frames_of(player).len() - 1);
+        assert!(kinematics.distance > 0.);
+        assert!(kinematics.top_speed > kinematics.average_speed);
+        assert_eq!(kinematics.air_time + kinematics.ground_time, inputs(&replay, player).duration);
+    }
+    let empty = kinematics(&Replay::new(), Player::Two, Time::from_ms(1000));
+    assert_eq!(empty, Kinematics::default());
+}
+
+#[test]
+fn inputs_timeline () {
+    let mut replay = moving_replay(10, &[]);
+    for n in [1, 2, 3, 6, 9].iter() {
+        replay.frames[*n].set_throttle(true);
+    }
+    // Turning right at frame 1.5 and back at frame 6.
+    for frame in &mut replay.frames[2..7] {
+        frame.set_right(true);
+    }
+    let event = |ms, event_type| Event::with_type(Time::from_ms(ms).event_time(), event_type);
+    replay.events = vec![event(50, EventType::Turn),
+                         event(60, EventType::Ground { alternative: true }),
+                         event(100, EventType::VoltRight),
+                         event(200, EventType::Turn),
+                         event(250, EventType::VoltLeft),
+                         event(260, EventType::VoltLeft)];
+
+    let inputs = inputs(&replay, Player::One);
+    assert_eq!(inputs.throttle, vec![(Time::from_frames(1), Time::from_frames(4)),
+                                     (Time::from_frames(6), Time::from_frames(7)),
+                                     (Time::from_frames(9), Time::from_frames(10))]);
+    assert_eq!(inputs.turns, vec![(Time::from_ms(50), true), (Time::from_ms(200), false)]);
+    assert_eq!(inputs.volts_right, vec![Time::from_ms(100)]);
+    assert_eq!(inputs.volts_left, vec![Time::from_ms(250), Time::from_ms(260)]);
+    assert_eq!(inputs.turn_count(), 2);
+    assert_eq!(inputs.volt_count(), 3);
+    assert!((inputs.throttle_percentage() - 50.).abs() < 0.5);
+}
+
+#[test]
+/// A turn at the first frame, or a missing turn event, doesn't change later directions.
+fn inputs_turn_directions_from_frames () {
+    let mut replay = moving_replay(10, &[]);
+    let turn = |frame: f64| Event::with_type(frame * FRAME_DURATION / EVENT_TIME_SCALE, EventType::Turn);
+    // Turns after frames 0, 4, 6 and 8, but the event after frame 6 is missing. The turn after
+    // the last frame flips the previous direction.
+    replay.events = vec![turn(0.5), turn(4.5), turn(8.5), turn(20.)];
+    for n in [1, 2, 3, 4, 7, 8].iter() {
+        replay.frames[*n].set_right(true);
+    }
+    let directions: Vec<_> = inputs(&replay, Player::One).turns.iter().map(|&(_, right)| right).collect();
+    assert_eq!(directions, vec![true, false, false, true]);
+}
+
+#[test]
+/// Directions after turns should match the frames recorded after them.
+fn inputs_turn_directions () {
+    for file in ["tests/assets/replays/test_3.rec", "tests/assets/replays/multi_event_unfinished.rec"].iter() {
+        let replay = Replay::load(file).unwrap();
+        let inputs = inputs(&replay, Player::One);
+        let frames = &replay.frames;
+        assert!(inputs.turn_count() > 0);
+        for &(time, right) in &inputs.turns {
+            assert_eq!(frames[time.frames() + 1].right(), right);
+        }
+        assert_eq!(inputs.duration, Time::from_frames(frames.len()));
+    }
+    let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    assert_eq!(inputs(&replay, Player::Two).volt_count(), 9);
+    assert_eq!(inputs(&Replay::new(), Player::One), Inputs::default());
+}
+
+#[test]
+fn apple_timeline_splits_and_gravity () {
+    let mut replay = Replay::new();
+    let touch = |ms, index| Event::with_type(Time::from_ms(ms).event_time(), EventType::Touch { index });
+    replay.events = vec![touch(1000, 1), touch(1500, 0), touch(2500, 2), touch(2600, 2), touch(3000, 5), touch(4000, 3)];
+    replay.events_2 = vec![touch(1200, 2), touch(1800, 4)];
+
+    let mut level = Level::new();
+    level.objects = vec![Object { position: Position { x: 0., y: 0. }, object_type: ObjectType::Player },
+                         Object { position: Position { x: 1., y: 2. }, object_type: ObjectType::Apple { gravity: Direction::Up, animation: 1 } },
+                         Object { position: Position { x: 3., y: 4. }, object_type: ObjectType::Apple { gravity: Direction::Normal, animation: 2 } },
+                         Object { position: Position { x: 5., y: 6. }, object_type: ObjectType::Exit },
+                         Object { position: Position { x: 7., y: 8. }, object_type: ObjectType::Apple { gravity: Direction::Left, animation: 1 } }];
+
+    let timeline = apple_timeline(&replay, &level);
+    assert_eq!(timeline.apples, vec![
+        Apple { player: Player::One, object: 1, position: Position { x: 1., y: 2. }, time: Time::from_ms(1000), split: Time::from_ms(1000) },
+        Apple { player: Player::Two, object: 2, position: Position { x: 3., y: 4. }, time: Time::from_ms(1200), split: Time::from_ms(200) },
+        Apple { player: Player::Two, object: 4, position: Position { x: 7., y: 8. }, time: Time::from_ms(1800), split: Time::from_ms(600) }
+    ]);
+    assert_eq!(timeline.gravity, vec![
+        GravityChange { player: Player::One, object: 1, time: Time::from_ms(1000), gravity: Direction::Up },
+        GravityChange { player: Player::Two, object: 4, time: Time::from_ms(1800), gravity: Direction::Left }
+    ]);
+}
+
+#[test]
+fn apple_timeline_replay () {
+    let replay = Replay::load("tests/assets/replays/test_3.rec").unwrap();
+    let mut level = Level::new();
+    level.objects = (0..5).map(|_| Object::new()).collect();
+    let timeline = apple_timeline(&replay, &level);
+    assert_eq!(timeline.apples.iter().map(|apple| apple.object).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    let total = timeline.apples.iter().fold(Time::default(), |total, apple| total + apple.split);
+    assert_eq!(total, timeline.apples[3].time);
+    assert_eq!(timeline.gravity, vec![]);
+}
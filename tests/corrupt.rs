@@ -0,0 +1,82 @@
+extern crate elma;
+
+use elma::ElmaError;
+use elma::lev::{ Level, ListEntry, parse_top10, write_top10 };
+use elma::lgr::Lgr;
+use elma::rec::Replay;
+use elma::state::State;
+use elma::time::Time;
+use std::env;
+use std::fs::{ self, File };
+use std::io::{ Read, Write };
+use std::path::Path;
+
+/// Loads the file as every supported file type. None of them should panic.
+fn load_all<P: AsRef<Path>> (path: P) -> (Result<Level, ElmaError>, Result<Replay, ElmaError>) {
+    let _ = Lgr::load(&path);
+    let _ = State::load(&path);
+    (Level::load(&path), Replay::load(&path))
+}
+
+#[test]
+/// Every file in the corrupt corpus should fail to load, without panicking.
+fn load_corrupt_corpus () {
+    let mut count = 0;
+    for entry in fs::read_dir("tests/assets/corrupt").unwrap() {
+        let path = entry.unwrap().path();
+        let (level, replay) = load_all(&path);
+        assert!(level.is_err(), "{:?} loaded as level", path);
+        assert!(replay.is_err(), "{:?} loaded as replay", path);
+        count += 1;
+    }
+    assert_eq!(count, 14);
+}
+
+#[test]
+fn load_corrupt_counts () {
+    assert_eq!(Level::load("tests/assets/corrupt/level_huge_polygon_count.lev").unwrap_err().root_cause(), &ElmaError::UnexpectedEnd);
+    assert_eq!(Level::load("tests/assets/corrupt/level_huge_vertex_count.lev").unwrap_err().root_cause(), &ElmaError::UnexpectedEnd);
+    assert_eq!(Level::load("tests/assets/corrupt/level_huge_object_count.lev").unwrap_err().root_cause(), &ElmaError::UnexpectedEnd);
+    assert_eq!(Replay::load("tests/assets/corrupt/replay_huge_frame_count.rec").unwrap_err().root_cause(), &ElmaError::UnexpectedEnd);
+    assert_eq!(Replay::load("tests/assets/corrupt/replay_huge_event_count.rec").unwrap_err().root_cause(), &ElmaError::UnexpectedEnd);
+    assert_eq!(Replay::load("tests/assets/corrupt/replay_negative_frame_count.rec").unwrap_err().root_cause(), &ElmaError::InvalidReplayFile);
+}
+
+#[test]
+fn parse_top10_huge_time () {
+    let mut data = vec![];
+    File::open("tests/assets/corrupt/top10_huge_time.dat").unwrap().read_to_end(&mut data).unwrap();
+    let list = parse_top10(&data).unwrap();
+    assert_eq!(list[0].time, Time::from_hundredths(i32::MAX));
+    assert_eq!(write_top10(&list).unwrap(), data);
+    let too_long = ListEntry { time: Time::from_hundredths(i32::MAX) + Time::from_ms(10), ..list[0].clone() };
+    assert_eq!(write_top10(&[too_long]).unwrap_err(), ElmaError::InvalidTimeFormat);
+}
+
+#[test]
+/// Every prefix of valid files should fail to parse, without panicking.
+fn parse_truncated_files () {
+    let files = ["tests/assets/levels/test_1.lev",
+                 "tests/assets/levels/across.lev",
+                 "tests/assets/replays/test_3.rec",
+                 "tests/assets/replays/multi_event_unfinished.rec"];
+    let mut dir = env::temp_dir();
+    dir.push("truncated_file");
+    for file in files.iter() {
+        let mut data = vec![];
+        File::open(file).unwrap().read_to_end(&mut data).unwrap();
+        for length in 0..data.len() {
+            let level = Level::from_bytes(&data[0..length]);
+            let replay = Replay::from_bytes(&data[0..length]);
+            assert!(level.is_err() && replay.is_err(), "{} parsed with length {}", file, length);
+        }
+        // LGRs and states are only loaded from files. Replays are larger, so only check every
+        // few lengths.
+        let step = if file.ends_with(".rec") { 7 } else { 1 };
+        for length in (0..data.len()).step_by(step) {
+            File::create(&dir).unwrap().write_all(&data[0..length]).unwrap();
+            let _ = Lgr::load(&dir);
+            let _ = State::load(&dir);
+        }
+    }
+}
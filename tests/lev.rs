@@ -1,10 +1,26 @@
 extern crate elma;
 extern crate rand;
 
-use elma::{ Position };
+use elma::{ Position, RandomSource, ZeroRng };
 use elma::lev::*;
+use elma::time::Time;
 use std::env;
-use rand::random;
+use std::fs::File;
+use std::io::Read;
+use rand::{ random, Rng, SeedableRng, XorShiftRng };
+
+// Seeded generator for the `_with_rng` methods.
+struct Seeded(XorShiftRng);
+
+impl Seeded {
+    fn new () -> Self {
+        Seeded(XorShiftRng::from_seed([1, 2, 3, 4]))
+    }
+}
+
+impl RandomSource for Seeded {
+    fn next_u32 (&mut self) -> u32 { self.0.next_u32() }
+}
 
 #[test]
 /// Generate random u8 data to simulate top10 lists, encrypting it and decrypting it,
@@ -71,26 +87,26 @@ fn construct_level_and_save () {
 fn overflow_top10_and_sort () {
     let mut level = Level::new();
     // Create more than 10 entries unordered.
-    let top10_single = vec![ListEntry { time: 2221, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 231, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 1221, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 10221, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 2321, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 22211, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 2201, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 5, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 5121, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 918, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 17, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                             ListEntry { time: 8172, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
+    let top10_single = vec![ListEntry { time: Time::from_hundredths(2221), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(231), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(1221), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(10221), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(2321), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(22211), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(2201), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(5), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(5121), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(918), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(17), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                             ListEntry { time: Time::from_hundredths(8172), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
     // Make multi list shorter, but still unordered.
-    let top10_multi = vec![ListEntry { time: 2221, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                            ListEntry { time: 231, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                            ListEntry { time: 2321, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                            ListEntry { time: 22211, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                            ListEntry { time: 918, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                            ListEntry { time: 17, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                            ListEntry { time: 8172, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
+    let top10_multi = vec![ListEntry { time: Time::from_hundredths(2221), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                            ListEntry { time: Time::from_hundredths(231), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                            ListEntry { time: Time::from_hundredths(2321), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                            ListEntry { time: Time::from_hundredths(22211), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                            ListEntry { time: Time::from_hundredths(918), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                            ListEntry { time: Time::from_hundredths(17), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                            ListEntry { time: Time::from_hundredths(8172), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
     level.top10_single = top10_single;
     level.top10_multi = top10_multi;
     // Save and then load it again to see whether it worked.
@@ -99,23 +115,23 @@ fn overflow_top10_and_sort () {
     level.save(&dir, true).unwrap();
     let level = Level::load(&dir).unwrap();
     // Check if we get the expected sorted times.
-    let expected_single = vec![ListEntry { time: 5, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 17, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 231, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 918, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 1221, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 2201, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 2221, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 2321, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 5121, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                        ListEntry { time: 8172, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
-    let expected_multi = vec![ListEntry { time: 17, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                                ListEntry { time: 231, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                                ListEntry { time: 918, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                                ListEntry { time: 2221, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                                ListEntry { time: 2321, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                                ListEntry { time: 8172, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
-                                ListEntry { time: 22211, name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
+    let expected_single = vec![ListEntry { time: Time::from_hundredths(5), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(17), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(231), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(918), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(1221), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(2201), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(2221), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(2321), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(5121), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                        ListEntry { time: Time::from_hundredths(8172), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
+    let expected_multi = vec![ListEntry { time: Time::from_hundredths(17), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                                ListEntry { time: Time::from_hundredths(231), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                                ListEntry { time: Time::from_hundredths(918), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                                ListEntry { time: Time::from_hundredths(2221), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                                ListEntry { time: Time::from_hundredths(2321), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                                ListEntry { time: Time::from_hundredths(8172), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() },
+                                ListEntry { time: Time::from_hundredths(22211), name_1: "test_p1".to_string(), name_2: "test_p2".to_string() }];
     assert_eq!(level.top10_single, expected_single);
     assert_eq!(level.top10_multi, expected_multi);
 }
@@ -126,19 +142,81 @@ fn load_invalid_level_path () {
 }
 
 #[test]
-/// This should error until Across support is added, if ever.
 fn load_across_level_1 () {
-    assert_eq!(Level::load("tests/assets/levels/across.lev").unwrap_err(), elma::ElmaError::AcrossUnsupported);
+    let level = Level::load("tests/assets/levels/across.lev").unwrap();
+    assert_eq!(level.version, Version::Across);
+    assert_eq!(level.link, 1915300293);
+    assert_eq!(level.integrity, [-448396.59889614285_f64,
+                                  460536.59889614285_f64,
+                                  460642.59889614285_f64,
+                                  476694.59889614285_f64]);
+    assert_eq!(level.name, "Killer Queen");
+    assert_eq!(level.polygons.len(), 3);
+    assert!(!level.polygons[0].grass);
+    assert_eq!(level.polygons[0].vertices.len(), 4);
+    assert_eq!(level.polygons[1].vertices.len(), 9);
+    assert_eq!(level.polygons[2].vertices.len(), 7);
+    assert_eq!(level.polygons[0].vertices[0], Position { x: -23.96842105263158_f64, y: -4.942105263157895_f64 });
+    assert_eq!(level.objects, vec![
+        Object {
+            position: Position { x: -15.773684210526316_f64, y: -1.636842105263156_f64 },
+            object_type: ObjectType::Exit
+        },
+        Object {
+            position: Position { x: 20.842105263157897_f64, y: -20.00526315789474_f64 },
+            object_type: ObjectType::Player
+        },
+        Object {
+            position: Position { x: 11.084210526315793_f64, y: -7.973684210526315_f64 },
+            object_type: ObjectType::Killer
+        },
+        Object {
+            position: Position { x: 10.942105263157899_f64, y: -12.436842105263157_f64 },
+            object_type: ObjectType::Killer
+        }
+    ]);
+    assert_eq!(level.pictures.len(), 0);
+    assert_eq!(level.top10_single.len(), 0);
+    assert_eq!(level.top10_multi.len(), 0);
 }
 
 #[test]
-/// Until Across is supported, should generate error when you try to save a Across level.
+/// Only the random parts of the integrity sums, `integrity[1]` and `integrity[2]`, and the
+/// garbage after the level name may differ.
+fn load_across_level_1_and_save () {
+    let mut original = vec![];
+    File::open("tests/assets/levels/across.lev").unwrap().read_to_end(&mut original).unwrap();
+    let mut level = Level::load("tests/assets/levels/across.lev").unwrap();
+    let mut dir = env::temp_dir();
+    dir.push("save_across_level_1.lev");
+    level.save(&dir, false).unwrap();
+    let mut saved = vec![];
+    File::open(&dir).unwrap().read_to_end(&mut saved).unwrap();
+    assert_eq!(saved.len(), original.len());
+    assert_eq!(&saved[0..17], &original[0..17]);
+    assert_eq!(&saved[33..41], &original[33..41]);
+    assert_eq!(&saved[41..53], b"Killer Queen");
+    assert_eq!(&saved[100..], &original[100..]);
+    assert_eq!(Level::load(&dir).unwrap().verify_integrity(), Ok(TopologyStatus::Ok));
+}
+
+#[test]
+/// Grass polygons, pictures, apple gravity and top10 lists can't be saved in an Across level.
 fn save_across_level_1 () {
-    let mut level = Level::new();
+    let mut level = Level::load("tests/assets/levels/test_1.lev").unwrap();
     level.version = Version::Across;
     let mut dir = env::temp_dir();
-    dir.push("save_across_level_1.lev");
-    assert_eq!(level.save(&dir, false).unwrap_err(), elma::ElmaError::AcrossUnsupported);
+    dir.push("save_across_level_2.lev");
+    level.save(&dir, true).unwrap();
+    let level_saved = Level::load(&dir).unwrap();
+    assert_eq!(level_saved.version, Version::Across);
+    assert_eq!(level_saved.name, "Rust test");
+    assert_eq!(level_saved.polygons.len(), 1);
+    assert_eq!(level_saved.polygons[0], level.polygons[0]);
+    assert_eq!(level_saved.objects.len(), 8);
+    assert_eq!(level_saved.objects[2].object_type, ObjectType::Apple { gravity: Direction::Normal, animation: 1 });
+    assert_eq!(level_saved.pictures.len(), 0);
+    assert_eq!(level_saved.top10_single.len(), 0);
 }
 
 #[test]
@@ -233,17 +311,17 @@ fn load_valid_level_1 () {
     assert_eq!(level.top10_single[0], ListEntry {
         name_1: String::from("Rust"),
         name_2: String::from("Cargo"),
-        time: 201
+        time: Time::from_hundredths(201)
     });
     assert_eq!(level.top10_single[2], ListEntry {
         name_1: String::from("Cargo"),
         name_2: String::from("Rust"),
-        time: 206
+        time: Time::from_hundredths(206)
     });
     assert_eq!(level.top10_single[9], ListEntry {
         name_1: String::from("Rust"),
         name_2: String::from("Cargo"),
-        time: 308
+        time: Time::from_hundredths(308)
     });
 }
 
@@ -256,7 +334,7 @@ fn load_valid_level_2 () {
     assert_eq!(level.ground, "brick");
     assert_eq!(level.sky, "ground");
     assert_eq!(level.polygons.len(), 5);
-    assert_eq!(level.polygons[0].grass, false);
+    assert!(!level.polygons[0].grass);
     assert_eq!(level.polygons[0].vertices.len(), 4);
     assert_eq!(level.polygons[0].vertices[0].x, 18.507991950076164);
     assert_eq!(level.polygons[0].vertices[1].y, 17.978810742022475);
@@ -303,20 +381,204 @@ fn load_valid_level_1_and_save_without_top10 () {
 
 #[test]
 fn load_invalid_level_1 () {
-    assert_eq!(Level::load("tests/assets/levels/invalid_1.lev").unwrap_err(), elma::ElmaError::InvalidLevelFile);
+    assert_eq!(Level::load("tests/assets/levels/invalid_1.lev").unwrap_err().root_cause(), &elma::ElmaError::InvalidLevelFile);
 }
 
 #[test]
 fn load_invalid_gravity_level_1 () {
-    assert_eq!(Level::load("tests/assets/levels/invalid_grav.lev").unwrap_err(), elma::ElmaError::InvalidGravity(6));
+    assert_eq!(Level::load("tests/assets/levels/invalid_grav.lev").unwrap_err(),
+               elma::ElmaError::Parse { offset: 1410, location: String::from("object #6"), error: Box::new(elma::ElmaError::InvalidGravity(6)) });
 }
 
 #[test]
 fn load_invalid_object_level_1 () {
-    assert_eq!(Level::load("tests/assets/levels/invalid_obj.lev").unwrap_err(), elma::ElmaError::InvalidObject(6));
+    assert_eq!(Level::load("tests/assets/levels/invalid_obj.lev").unwrap_err(),
+               elma::ElmaError::Parse { offset: 1522, location: String::from("object #10"), error: Box::new(elma::ElmaError::InvalidObject(6)) });
+}
+
+#[test]
+fn load_invalid_object_across_level () {
+    let mut data = vec![];
+    File::open("tests/assets/levels/across.lev").unwrap().read_to_end(&mut data).unwrap();
+    // Type of object #2, with objects starting at 448.
+    data[448 + 2 * 20 + 16] = 6;
+    assert_eq!(Level::from_bytes(data).unwrap_err(),
+               elma::ElmaError::Parse { offset: 488, location: String::from("object #2"), error: Box::new(elma::ElmaError::InvalidObject(6)) });
 }
 
 #[test]
 fn load_invalid_clip_level_1 () {
-    assert_eq!(Level::load("tests/assets/levels/invalid_clip.lev").unwrap_err(), elma::ElmaError::InvalidClipping(3));
+    assert_eq!(Level::load("tests/assets/levels/invalid_clip.lev").unwrap_err(),
+               elma::ElmaError::Parse { offset: 1834, location: String::from("picture #2"), error: Box::new(elma::ElmaError::InvalidClipping(3)) });
+}
+
+#[test]
+fn level_bounding_box () {
+    let mut level = Level::new();
+    level.polygons = vec![];
+    level.objects = vec![];
+    assert_eq!(level.bounding_box(), None);
+    assert_eq!(Polygon::new().bounding_box(), None);
+    assert_eq!(level.width(), 0_f64);
+
+    // Level entirely away from the origin.
+    level.polygons.push(Polygon { grass: false, vertices: vec![
+        Position { x: 100_f64, y: -50_f64 },
+        Position { x: 110_f64, y: -50_f64 },
+        Position { x: 110_f64, y: -40_f64 }]});
+    assert_eq!(level.polygons[0].bounding_box().unwrap(),
+               [Position { x: 100_f64, y: -40_f64 }, Position { x: 110_f64, y: -40_f64 },
+                Position { x: 100_f64, y: -50_f64 }, Position { x: 110_f64, y: -50_f64 }]);
+    assert_eq!(level.width(), 10_f64);
+    assert_eq!(level.height(), 10_f64);
+
+    // Objects and pictures extend the level box, but not the width.
+    level.objects.push(Object { position: Position { x: 120_f64, y: -45_f64 }, object_type: ObjectType::Exit });
+    level.pictures.push(Picture::new());
+    level.pictures[0].position = Position { x: 105_f64, y: -60_f64 };
+    assert_eq!(level.bounding_box().unwrap(),
+               [Position { x: 100_f64, y: -40_f64 }, Position { x: 120.4_f64, y: -40_f64 },
+                Position { x: 100_f64, y: -60_f64 }, Position { x: 120.4_f64, y: -60_f64 }]);
+    assert_eq!(level.width(), 10_f64);
+}
+
+#[test]
+fn level_from_reader_and_write_to () {
+    let mut level = Level::load("tests/assets/levels/test_1.lev").unwrap();
+    let mut bytes = vec![];
+    level.write_to(&mut bytes, true).unwrap();
+    assert_eq!(bytes, level.raw);
+    let level_read = Level::from_reader(std::io::Cursor::new(&bytes)).unwrap();
+    let level_bytes = Level::from_bytes(&bytes[..]).unwrap();
+    assert_eq!(level_read, level_bytes);
+    assert_eq!(level_read.polygons, level.polygons);
+    assert_eq!(level_read.objects, level.objects);
+    assert_eq!(level_read.top10_single, level.top10_single);
+    assert_eq!(Level::from_bytes(vec![]).unwrap_err().root_cause(), &elma::ElmaError::UnexpectedEnd);
+}
+
+#[test]
+/// Unmodified levels should be written back byte for byte, including garbage bytes.
+fn load_level_and_update_lossless () {
+    for file in ["tests/assets/levels/test_1.lev", "tests/assets/levels/test_2.lev", "tests/assets/levels/across.lev"].iter() {
+        let mut level = Level::load(file).unwrap();
+        let original = level.raw.clone();
+        level.update_lossless().unwrap();
+        assert_eq!(level.raw, original, "{}", file);
+    }
+
+    // Garbage after the name terminator and in the unused link bytes.
+    let mut level = Level::load("tests/assets/levels/test_1.lev").unwrap();
+    level.raw[5] ^= 0xFF;
+    level.raw[43 + 30] = b'x';
+    let original = level.raw.clone();
+    let mut level = Level::from_bytes(original.clone()).unwrap();
+    level.update_lossless().unwrap();
+    assert_eq!(level.raw, original);
+}
+
+#[test]
+/// Only edited parts should be re-encoded.
+fn load_level_modify_and_update_lossless () {
+    let mut level = Level::load("tests/assets/levels/test_1.lev").unwrap();
+    level.raw[43 + 30] = b'x';
+    let mut level = Level::from_bytes(level.raw.clone()).unwrap();
+    let original = level.raw.clone();
+    let integrity = level.integrity;
+
+    // Editing the LGR name keeps everything else.
+    level.lgr = String::from("custom");
+    level.update_lossless().unwrap();
+    assert_eq!(level.raw.len(), original.len());
+    assert_eq!(&level.raw[0..43 + 51], &original[0..43 + 51]);
+    assert_eq!(&level.raw[43 + 51..43 + 67], &b"custom\0\0\0\0\0\0\0\0\0\0"[..]);
+    assert_eq!(&level.raw[43 + 67..], &original[43 + 67..]);
+    assert_eq!(level.integrity, integrity);
+
+    // Editing objects recalculates integrity sums, but keeps top10 bytes.
+    level.objects[0].position.x += 1_f64;
+    level.update_lossless().unwrap();
+    assert!(level.integrity != integrity);
+    let length = level.raw.len();
+    assert_eq!(&level.raw[length - 692..], &original[length - 692..]);
+    let saved = Level::from_bytes(level.raw.clone()).unwrap();
+    assert_eq!(saved.objects, level.objects);
+    assert_eq!(saved.lgr, "custom");
+
+    // Editing top10 lists re-encodes them.
+    level.top10_single.push(ListEntry { time: Time::from_hundredths(1), name_1: String::from("A"), name_2: String::from("B") });
+    level.update_lossless().unwrap();
+    let saved = Level::from_bytes(level.raw.clone()).unwrap();
+    assert_eq!(saved.top10_single[0].time, Time::from_hundredths(1));
+}
+
+#[test]
+fn new_level_update_lossless () {
+    let mut level = Level::new();
+    level.update_lossless().unwrap();
+    let saved = Level::from_bytes(level.raw.clone()).unwrap();
+    assert_eq!(saved.polygons, level.polygons);
+}
+
+#[test]
+/// The same generator should give the same bytes every time.
+fn level_write_with_rng () {
+    let mut level = Level::load("tests/assets/levels/test_1.lev").unwrap();
+    let mut first = vec![];
+    let mut second = vec![];
+    level.write_to_with_rng(&mut first, true, &mut Seeded::new()).unwrap();
+    level.write_to_with_rng(&mut second, true, &mut Seeded::new()).unwrap();
+    assert_eq!(first, second);
+
+    level.update_with_rng(true, &mut ZeroRng).unwrap();
+    let sum = level.integrity[0];
+    assert_eq!(level.integrity[1], 11877. - sum);
+    assert_eq!(level.integrity[2], 11877. - sum);
+    assert_eq!(level.integrity[3], 12112. - sum);
+
+    let mut level = Level::new_with_rng(&mut ZeroRng);
+    assert_eq!(level.link, 0);
+    level.generate_link_with_rng(&mut Seeded::new());
+    assert_eq!(level.link, XorShiftRng::from_seed([1, 2, 3, 4]).next_u32());
+}
+
+#[test]
+fn level_verify_integrity () {
+    let level = Level::load("tests/assets/levels/test_1.lev").unwrap();
+    assert_eq!(level.verify_integrity(), Ok(TopologyStatus::Ok));
+    let level = Level::load("tests/assets/levels/across.lev").unwrap();
+    assert_eq!(level.verify_integrity(), Ok(TopologyStatus::Ok));
+    // Saved with intersecting polygons.
+    let level = Level::load("tests/assets/levels/topology_intersect_1.lev").unwrap();
+    assert_eq!(level.verify_integrity(), Ok(TopologyStatus::Error));
+
+    let mut level = Level::new();
+    level.update(false).unwrap();
+    assert_eq!(level.verify_integrity(), Ok(TopologyStatus::Ok));
+    let sum = level.integrity[0];
+    level.integrity[1] = 17748. - sum;
+    assert_eq!(level.verify_integrity(), Err(elma::ElmaError::InvalidIntegrity(1)));
+    level.integrity[1] = 11877. - sum;
+    level.integrity[2] = 20000. - sum;
+    assert_eq!(level.verify_integrity(), Err(elma::ElmaError::InvalidIntegrity(2)));
+    level.integrity[2] = 20961. - sum;
+    assert_eq!(level.verify_integrity(), Ok(TopologyStatus::Error));
+    level.integrity[3] = 0.;
+    assert_eq!(level.verify_integrity(), Err(elma::ElmaError::InvalidIntegrity(3)));
+    level.polygons[0].vertices[0].x += 0.5;
+    assert_eq!(level.verify_integrity(), Err(elma::ElmaError::InvalidIntegrity(0)));
+}
+
+#[test]
+fn level_verify_integrity_across () {
+    let mut level = Level::load("tests/assets/levels/test_1.lev").unwrap();
+    level.version = Version::Across;
+    let mut saved = Level::from_bytes(level.get_raw(false).unwrap()).unwrap();
+    assert_eq!(saved.version, Version::Across);
+    assert_eq!(saved.verify_integrity(), Ok(TopologyStatus::Ok));
+    // The last sum isn't checked, but the others are.
+    saved.integrity[3] = 12112. - saved.integrity[0];
+    assert_eq!(saved.verify_integrity(), Ok(TopologyStatus::Ok));
+    saved.integrity[1] = 0.;
+    assert_eq!(saved.verify_integrity(), Err(elma::ElmaError::InvalidIntegrity(1)));
 }
@@ -0,0 +1,88 @@
+extern crate elma;
+
+use elma::lev::Clip;
+use elma::lgr::*;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+#[test]
+/// Build an LGR with some arbitrary values, save and load it again.
+fn construct_lgr_save_and_load () {
+    let mut lgr = Lgr::new();
+    lgr.pictures.push(PictureData::new("barrel"));
+    lgr.pictures.push(PictureData {
+        name: String::from("stone1"),
+        picture_type: PictureType::Texture,
+        distance: 750,
+        clip: Clip::Ground,
+        transparency: Transparency::Solid
+    });
+    lgr.pictures.push(PictureData {
+        name: String::from("maskbig"),
+        picture_type: PictureType::Mask,
+        distance: 500,
+        clip: Clip::Unclipped,
+        transparency: Transparency::BottomRight
+    });
+    lgr.images.push(LgrImage { name: String::from("barrel.pcx"), data: vec![10, 5, 1, 8], ..LgrImage::default() });
+    lgr.images.push(LgrImage { name: String::from("stone1.pcx"), data: vec![], ..LgrImage::default() });
+
+    let mut dir = env::temp_dir();
+    dir.push("construct.lgr");
+    lgr.save(&dir).unwrap();
+    let lgr_saved = Lgr::load(&dir).unwrap();
+    assert_eq!(lgr, lgr_saved);
+    assert_eq!(lgr_saved.picture("Stone1").unwrap().picture_type, PictureType::Texture);
+    assert_eq!(lgr_saved.image("BARREL.PCX").unwrap().data, vec![10, 5, 1, 8]);
+    assert!(lgr_saved.picture("stone2").is_none());
+}
+
+#[test]
+/// constructed.lgr was written by a separate encoder following the LGR format, not by this
+/// crate. Its images have non-zero unknown bytes, which should be written back unchanged.
+fn load_constructed_lgr_and_save () {
+    let mut lgr = Lgr::load("tests/assets/lgr/constructed.lgr").unwrap();
+    assert_eq!(lgr.pictures.len(), 3);
+    assert_eq!(lgr.pictures[0], PictureData { distance: 400, clip: Clip::Ground, ..PictureData::new("barrel") });
+    assert_eq!(lgr.pictures[1].picture_type, PictureType::Texture);
+    assert_eq!(lgr.pictures[2].transparency, Transparency::BottomRight);
+    assert_eq!(lgr.images.len(), 2);
+    assert_eq!(lgr.images[0].unknown, [0x10, 0x27, 0x5B, 0x01]);
+    let barrel = lgr.images[0].decode().unwrap();
+    assert_eq!((barrel.width, barrel.height), (5, 3));
+    assert_eq!(barrel.pixels[5..10], [7, 200, 201, 200, 7]);
+    assert_eq!(lgr.image("stone1.pcx").unwrap().decode().unwrap().pixels, vec![1, 2, 3, 4, 4, 3, 2, 1]);
+
+    let mut original = vec![];
+    File::open("tests/assets/lgr/constructed.lgr").unwrap().read_to_end(&mut original).unwrap();
+    let mut dir = env::temp_dir();
+    dir.push("constructed.lgr");
+    lgr.save(&dir).unwrap();
+    let mut saved = vec![];
+    File::open(&dir).unwrap().read_to_end(&mut saved).unwrap();
+    assert_eq!(saved, original);
+}
+
+#[test]
+fn lgr_from_reader_and_write_to () {
+    let mut lgr = Lgr::load("tests/assets/lgr/constructed.lgr").unwrap();
+    let mut bytes = vec![];
+    lgr.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes, lgr.raw);
+    let lgr_read = Lgr::from_reader(std::io::Cursor::new(&bytes)).unwrap();
+    let lgr_bytes = Lgr::from_bytes(&bytes[..]).unwrap();
+    assert_eq!(lgr_read, lgr_bytes);
+    assert_eq!(lgr_read, lgr);
+    assert_eq!(Lgr::from_bytes(vec![]).unwrap_err(), elma::ElmaError::UnexpectedEnd);
+}
+
+#[test]
+fn load_invalid_lgr () {
+    assert_eq!(Lgr::load("tests/assets/levels/test_1.lev").unwrap_err(), elma::ElmaError::InvalidLgrFile);
+}
+
+#[test]
+fn load_invalid_lgr_path () {
+    assert_eq!(Lgr::load("tests/assets/missing.lgr").unwrap_err(), elma::ElmaError::Io(std::io::ErrorKind::NotFound));
+}
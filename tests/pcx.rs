@@ -0,0 +1,95 @@
+extern crate elma;
+
+use elma::lgr::{ LgrImage, Transparency };
+use elma::pcx::*;
+
+/// Image with long runs, odd width and palette indexes that need escaping.
+fn test_image () -> Pcx {
+    let mut image = Pcx::new(71, 3);
+    for n in 0..256 {
+        image.palette[n] = [n as u8, 255 - n as u8, (n / 2) as u8];
+    }
+    for (n, pixel) in image.pixels.iter_mut().enumerate() {
+        *pixel = match n {
+            0..=69 => 7,
+            70..=99 => 0xC5,
+            _ => (n % 256) as u8
+        };
+    }
+    image
+}
+
+#[test]
+fn encode_decode_pcx () {
+    let image = test_image();
+    let encoded = image.encode().unwrap();
+    // Width 71 padded to 72 bytes per line.
+    assert_eq!(&encoded[66..68], &[72, 0]);
+    assert_eq!(encoded[encoded.len() - 769], 0x0C);
+    assert_eq!(Pcx::decode(&encoded).unwrap(), image);
+}
+
+#[test]
+/// Some encoders let runs continue on the next scanline.
+fn decode_pcx_run_across_scanlines () {
+    let mut data = Pcx::new(2, 2).encode().unwrap();
+    let palette = data.split_off(128);
+    data.extend_from_slice(&[0xC3, 9, 4]);
+    data.extend_from_slice(&palette[palette.len() - 769..]);
+    let image = Pcx::decode(&data).unwrap();
+    assert_eq!(image.pixels, vec![9, 9, 9, 4]);
+}
+
+#[test]
+fn decode_lgr_image () {
+    let image = test_image();
+    let lgr_image = LgrImage { name: String::from("test.pcx"), data: image.encode().unwrap(), ..LgrImage::default() };
+    assert_eq!(lgr_image.decode().unwrap(), image);
+}
+
+#[test]
+fn pcx_transparency_and_rgba () {
+    let mut image = Pcx::new(2, 2);
+    image.pixels = vec![1, 2, 3, 4];
+    image.palette[3] = [10, 20, 30];
+    assert_eq!(image.transparent_index(&Transparency::Solid), None);
+    assert_eq!(image.transparent_index(&Transparency::Palette), Some(0));
+    assert_eq!(image.transparent_index(&Transparency::TopLeft), Some(1));
+    assert_eq!(image.transparent_index(&Transparency::TopRight), Some(2));
+    assert_eq!(image.transparent_index(&Transparency::BottomLeft), Some(3));
+    assert_eq!(image.transparent_index(&Transparency::BottomRight), Some(4));
+    let rgba = image.to_rgba(Some(1));
+    assert_eq!(rgba.len(), 16);
+    assert_eq!(&rgba[0..4], &[0, 0, 0, 0]);
+    assert_eq!(&rgba[8..12], &[10, 20, 30, 255]);
+}
+
+#[test]
+fn decode_invalid_pcx () {
+    assert_eq!(Pcx::decode(&[0x0A, 5, 1, 8]).unwrap_err(), elma::ElmaError::InvalidPcxFile);
+    let mut data = test_image().encode().unwrap();
+    // 4 bits per pixel.
+    data[3] = 4;
+    assert_eq!(Pcx::decode(&data).unwrap_err(), elma::ElmaError::InvalidPcxFile);
+    // Truncated image data.
+    let data = test_image().encode().unwrap();
+    let mut truncated = data[0..200].to_vec();
+    truncated.extend_from_slice(&data[data.len() - 769..]);
+    assert_eq!(Pcx::decode(&truncated).unwrap_err(), elma::ElmaError::InvalidPcxFile);
+    // Width of 65536 pixels.
+    let mut data = test_image().encode().unwrap();
+    data[4..6].copy_from_slice(&[0, 0]);
+    data[8..10].copy_from_slice(&[0xFF, 0xFF]);
+    data[66..68].copy_from_slice(&[0xFF, 0xFF]);
+    assert_eq!(Pcx::decode(&data).unwrap_err(), elma::ElmaError::InvalidPcxFile);
+}
+
+#[test]
+fn encode_invalid_pcx () {
+    let mut image = Pcx::new(4, 4);
+    image.pixels.pop();
+    assert_eq!(image.encode().unwrap_err(), elma::ElmaError::InvalidPcxFile);
+    // Scanlines would be padded to 65536 bytes.
+    assert_eq!(Pcx::new(65535, 1).encode().unwrap_err(), elma::ElmaError::InvalidPcxFile);
+    assert!(Pcx::new(65534, 1).encode().is_ok());
+}
@@ -1,7 +1,12 @@
+// Expected values are copied verbatim from the replay files.
+#![allow(clippy::excessive_precision)]
+
 extern crate elma;
 
 use elma::{ Position };
 use elma::rec::*;
+use elma::lev::{ Direction, Level, Object, ObjectType };
+use elma::time::{ Time, EVENT_TIME_SCALE, FRAME_DURATION };
 use std::env;
 
 #[test]
@@ -17,19 +22,21 @@ fn rec_default_values () {
         rotation: 0,
         left_wheel_rotation: 0,
         right_wheel_rotation: 0,
-        throttle: false,
-        right: false,
+        flags: 0,
         volume: 0
     });
     let event = Event::new();
     assert_eq!(event, Event {
         time: 0_f64,
-        event_type: EventType::Touch { index: 0 }
+        event_type: EventType::Touch { index: 0 },
+        volume: 0_f32,
+        unknown: 0
     });
     let mut replay = Replay::new();
     replay.link = 1239;
     assert_eq!(replay, Replay {
         raw: vec![],
+        version: Version::Elma,
         multi: false,
         flag_tag: false,
         link: 1239,
@@ -37,7 +44,9 @@ fn rec_default_values () {
         frames: vec![],
         events: vec![],
         frames_2: vec![],
-        events_2: vec![]
+        events_2: vec![],
+        unknown: 0,
+        unknown_2: 0
     });
 }
 
@@ -49,8 +58,8 @@ fn load_invalid_replay_path () {
 #[test]
 fn load_valid_replay_1 () {
     let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
-    assert_eq!(replay.multi, false);
-    assert_eq!(replay.flag_tag, false);
+    assert!(!replay.multi);
+    assert!(!replay.flag_tag);
     assert_eq!(replay.link, 2549082363);
     assert_eq!(replay.level, "tutor14.lev");
 
@@ -64,8 +73,7 @@ fn load_valid_replay_1 () {
         rotation: 10000,
         left_wheel_rotation: 250,
         right_wheel_rotation: 0,
-        throttle: true,
-        right: false,
+        flags: 0xCD,
         volume: 5120
     });
     assert_eq!(replay.frames[100], Frame {
@@ -76,8 +84,7 @@ fn load_valid_replay_1 () {
         rotation: 9826,
         left_wheel_rotation: 248,
         right_wheel_rotation: 238,
-        throttle: true,
-        right: false,
+        flags: 0xAD,
         volume: -5398
     });
     assert_eq!(replay.frames[201], Frame {
@@ -88,8 +95,7 @@ fn load_valid_replay_1 () {
         rotation: 7325,
         left_wheel_rotation: 25,
         right_wheel_rotation: 23,
-        throttle: true,
-        right: false,
+        flags: 0xDD,
         volume: -5398
     });
     assert_eq!(replay.frames[439], Frame {
@@ -100,8 +106,7 @@ fn load_valid_replay_1 () {
         rotation: 9047,
         left_wheel_rotation: 73,
         right_wheel_rotation: 163,
-        throttle: true,
-        right: false,
+        flags: 0x1D,
         volume: 5652
     });
 
@@ -109,27 +114,35 @@ fn load_valid_replay_1 () {
     assert_eq!(replay.events.len(), 24);
     assert_eq!(replay.events[0], Event {
         time: 1.57728480001688_f64,
-        event_type: EventType::VoltRight
+        event_type: EventType::VoltRight,
+        volume: 0.99_f32,
+        unknown: 0
      });
     assert_eq!(replay.events[1], Event {
         time: 1.6974048000097273_f64,
-        event_type: EventType::Ground { alternative: false }
+        event_type: EventType::Ground { alternative: false },
+        volume: 0.72119284_f32,
+        unknown: 0
      });
     assert_eq!(replay.events[11], Event {
         time: 3.9464880000114437_f64,
-        event_type: EventType::VoltLeft
+        event_type: EventType::VoltLeft,
+        volume: 0.99_f32,
+        unknown: 0
      });
     assert_eq!(replay.events[23], Event {
         time: 6.398683200001716_f64,
-        event_type: EventType::Touch { index: 3 }
+        event_type: EventType::Touch { index: 3 },
+        volume: 0_f32,
+        unknown: 0
      });
 }
 
 #[test]
 fn load_valid_multi_replay_1 () {
     let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
-    assert_eq!(replay.multi, true);
-    assert_eq!(replay.flag_tag, false);
+    assert!(replay.multi);
+    assert!(!replay.flag_tag);
     assert_eq!(replay.link, 2549082363);
     assert_eq!(replay.level, "tutor14.lev");
     assert_eq!(replay.frames.len(), 440);
@@ -141,8 +154,7 @@ fn load_valid_multi_replay_1 () {
         rotation: 9047,
         left_wheel_rotation: 73,
         right_wheel_rotation: 163,
-        throttle: true,
-        right: false,
+        flags: 0x1D,
         volume: 5652
     });
     assert_eq!(replay.events.len(), 24);
@@ -203,119 +215,453 @@ fn load_valid_multi_replay_1_and_save () {
     assert_eq!(replay.events_2, replay_saved.events_2);
 }
 
+#[test]
+/// Across replays have no multi-player part or frame volume.
+fn save_across_replay_and_load () {
+    let mut replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    replay.version = Version::Across;
+    let mut dir = env::temp_dir();
+    dir.push("save_across_replay_1.rec");
+    assert_eq!(replay.save(&dir).unwrap_err(), elma::ElmaError::AcrossMultiPlayer);
+    replay.multi = false;
+    replay.save(&dir).unwrap();
+    let replay_saved = Replay::load(&dir).unwrap();
+    assert_eq!(replay_saved.version, Version::Across);
+    assert!(!replay_saved.multi);
+    assert!(!replay_saved.flag_tag);
+    assert_eq!(replay.link, replay_saved.link);
+    assert_eq!(replay.level, replay_saved.level);
+    assert_eq!(replay_saved.frames.len(), 440);
+    assert_eq!(replay_saved.frames[439], Frame {
+        bike: Position { x: -34.779712677002_f32, y: 11.526465415955_f32 },
+        left_wheel: Position { x: -1050, y: -33 },
+        right_wheel: Position { x: 286, y: -757 },
+        head: Position { x: 226, y: 376 },
+        rotation: 9047,
+        left_wheel_rotation: 73,
+        right_wheel_rotation: 163,
+        flags: 0x1D,
+        volume: 0
+    });
+    assert_eq!(replay.events, replay_saved.events);
+    assert_eq!(replay_saved.frames_2.len(), 0);
+    assert_eq!(replay_saved.events_2.len(), 0);
+    assert_eq!(replay_saved.raw.len(), 12 + 12 + 440 * 25 + 4 + 24 * 16);
+}
+
+#[test]
+/// across_converted.rec is unfinished.rec laid out by hand as an Across replay: 0x82 header
+/// without the multi-player, flag-tag and garbage fields, 25-byte frames without volume, and
+/// no end-of-replay marker.
+fn load_across_replay_and_save_byte_for_byte () {
+    let data = std::fs::read("tests/assets/replays/across_converted.rec").unwrap();
+    let replay = Replay::load("tests/assets/replays/across_converted.rec").unwrap();
+    assert_eq!(replay.version, Version::Across);
+    assert_eq!(replay.raw, data);
+    let mut elma = Replay::load("tests/assets/replays/unfinished.rec").unwrap();
+    assert_eq!(replay.link, elma.link);
+    assert_eq!(replay.level, elma.level);
+    for frame in &mut elma.frames {
+        frame.volume = 0;
+    }
+    assert_eq!(replay.frames.len(), 16);
+    assert_eq!(replay.frames, elma.frames);
+    let mut bytes = vec![];
+    replay.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes, data);
+}
+
+#[test]
+/// across_converted_2.rec is test_1.rec laid out the same way, so it has events with the
+/// recorded ground impact volumes.
+fn load_across_replay_with_events_and_save_byte_for_byte () {
+    let data = std::fs::read("tests/assets/replays/across_converted_2.rec").unwrap();
+    let replay = Replay::load("tests/assets/replays/across_converted_2.rec").unwrap();
+    let elma = Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    assert_eq!(replay.version, Version::Across);
+    assert_eq!(replay.events, elma.events);
+    assert_eq!(replay.events[1].volume, 0.72119284_f32);
+    let mut bytes = vec![];
+    replay.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes, data);
+}
+
+#[test]
+/// Event volumes and unknown values are written back exactly.
+fn load_replays_and_save_byte_for_byte () {
+    for file in ["test_1.rec", "test_2.rec", "test_3.rec", "unfinished.rec", "event_unfinished.rec",
+                 "multi_event_unfinished.rec", "multi_event_unfinished_2.rec"].iter() {
+        let replay = Replay::load(format!("tests/assets/replays/{}", file)).unwrap();
+        let mut bytes = vec![];
+        replay.write_to(&mut bytes).unwrap();
+        assert!(bytes == replay.raw, "{} changed when saved", file);
+    }
+    let mut replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    replay.unknown = 1;
+    replay.unknown_2 = 2;
+    replay.events[1].unknown = 3;
+    let mut bytes = vec![];
+    replay.write_to(&mut bytes).unwrap();
+    let saved = Replay::from_bytes(bytes).unwrap();
+    assert_eq!((saved.unknown, saved.unknown_2, saved.events[1].unknown), (1, 2, 3));
+}
+
+#[test]
+fn load_invalid_replay_version () {
+    let mut data = std::fs::read("tests/assets/replays/test_1.rec").unwrap();
+    data[4] = 0x84;
+    let mut dir = env::temp_dir();
+    dir.push("invalid_version.rec");
+    std::fs::write(&dir, &data).unwrap();
+    assert_eq!(Replay::load(&dir).unwrap_err().root_cause(), &elma::ElmaError::InvalidReplayFile);
+}
+
 #[test]
 fn load_invalid_event_replay () {
-    assert_eq!(Replay::load("tests/assets/replays/invalid_event.rec").unwrap_err(), elma::ElmaError::InvalidEvent(8));
+    assert_eq!(Replay::load("tests/assets/replays/invalid_event.rec").unwrap_err(),
+               elma::ElmaError::Parse { offset: 12192, location: String::from("player 1 event #17"), error: Box::new(elma::ElmaError::InvalidEvent(8)) });
 }
 
 #[test]
 fn replay_get_time_ms_finished_single () {
     let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
     let (time, finished) = replay.get_time_ms();
-    assert_eq!(time, 14649);
-    assert_eq!(finished, true);
+    assert_eq!(time.ms(), 14649);
+    assert!(finished);
 }
 
 #[test]
 fn replay_get_time_ms_finished_multi () {
     let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
     let (time, finished) = replay.get_time_ms();
-    assert_eq!(time, 14671);
-    assert_eq!(finished, true);
+    assert_eq!(time.ms(), 14671);
+    assert!(finished);
 }
 
 #[test]
 fn replay_get_time_ms_unfinished_no_event () {
     let replay = Replay::load("tests/assets/replays/unfinished.rec").unwrap();
     let (time, finished) = replay.get_time_ms();
-    assert_eq!(time, 533);
-    assert_eq!(finished, false);
+    assert_eq!(time.ms(), 533);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_ms_unfinished_event_single () {
     let replay = Replay::load("tests/assets/replays/test_3.rec").unwrap();
     let (time, finished) = replay.get_time_ms();
-    assert_eq!(time, 4767);
-    assert_eq!(finished, false);
+    assert_eq!(time.ms(), 4767);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_ms_unfinished_event_multi () {
     let replay = Replay::load("tests/assets/replays/multi_event_unfinished.rec").unwrap();
     let (time, finished) = replay.get_time_ms();
-    assert_eq!(time, 1600);
-    assert_eq!(finished, false);
+    assert_eq!(time.ms(), 1600);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_ms_unfinished_event_multi_2 () {
     let replay = Replay::load("tests/assets/replays/multi_event_unfinished_2.rec").unwrap();
     let (time, finished) = replay.get_time_ms();
-    assert_eq!(time, 3233);
-    assert_eq!(finished, false);
+    assert_eq!(time.ms(), 3233);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_ms_unfinished_event_single_2_frame_diff () {
     let replay = Replay::load("tests/assets/replays/event_unfinished.rec").unwrap();
     let (time, finished) = replay.get_time_ms();
-    assert_eq!(time, 8567);
-    assert_eq!(finished, false);
+    assert_eq!(time.ms(), 8567);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_hs_finished_single () {
     let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
     let (time, finished) = replay.get_time_hs();
-    assert_eq!(time, 1464);
-    assert_eq!(finished, true);
+    assert_eq!(time.hundredths(), 1464);
+    assert!(finished);
 }
 
 #[test]
 fn replay_get_time_hs_finished_multi () {
     let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
     let (time, finished) = replay.get_time_hs();
-    assert_eq!(time, 1467);
-    assert_eq!(finished, true);
+    assert_eq!(time.hundredths(), 1467);
+    assert!(finished);
 }
 
 #[test]
 fn replay_get_time_hs_unfinished_no_event () {
     let replay = Replay::load("tests/assets/replays/unfinished.rec").unwrap();
     let (time, finished) = replay.get_time_hs();
-    assert_eq!(time, 53);
-    assert_eq!(finished, false);
+    assert_eq!(time.hundredths(), 53);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_hs_unfinished_event_single () {
     let replay = Replay::load("tests/assets/replays/test_3.rec").unwrap();
     let (time, finished) = replay.get_time_hs();
-    assert_eq!(time, 476);
-    assert_eq!(finished, false);
+    assert_eq!(time.hundredths(), 476);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_hs_unfinished_event_multi () {
     let replay = Replay::load("tests/assets/replays/multi_event_unfinished.rec").unwrap();
     let (time, finished) = replay.get_time_hs();
-    assert_eq!(time, 160);
-    assert_eq!(finished, false);
+    assert_eq!(time.hundredths(), 160);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_hs_unfinished_event_multi_2 () {
     let replay = Replay::load("tests/assets/replays/multi_event_unfinished_2.rec").unwrap();
     let (time, finished) = replay.get_time_hs();
-    assert_eq!(time, 323);
-    assert_eq!(finished, false);
+    assert_eq!(time.hundredths(), 323);
+    assert!(!finished);
 }
 
 #[test]
 fn replay_get_time_hs_unfinished_event_single_2_frame_diff () {
     let replay = Replay::load("tests/assets/replays/event_unfinished.rec").unwrap();
     let (time, finished) = replay.get_time_hs();
-    assert_eq!(time, 856);
-    assert_eq!(finished, false);
+    assert_eq!(time.hundredths(), 856);
+    assert!(!finished);
+}
+
+#[test]
+fn replay_from_reader_and_write_to () {
+    let replay = Replay::load("tests/assets/replays/multi_event_unfinished.rec").unwrap();
+    let mut bytes = vec![];
+    replay.write_to(&mut bytes).unwrap();
+    let replay_read = Replay::from_reader(std::io::Cursor::new(&bytes)).unwrap();
+    let replay_bytes = Replay::from_bytes(&bytes[..]).unwrap();
+    assert!(replay_read.multi);
+    assert_eq!(replay_read.frames, replay.frames);
+    assert_eq!(replay_read.events_2, replay.events_2);
+    assert_eq!(replay_read.frames_2, replay_bytes.frames_2);
+    assert_eq!(replay_bytes.raw, bytes);
+    assert_eq!(Replay::from_bytes(vec![1, 2, 3]).unwrap_err().root_cause(), &elma::ElmaError::UnexpectedEnd);
+}
+
+#[test]
+fn load_invalid_event_replay_player_2 () {
+    let mut data = std::fs::read("tests/assets/replays/multi_event_unfinished_2.rec").unwrap();
+    // Type of the last of the nine events of player two.
+    let offset = data.len() - 4 - 16;
+    data[offset + 10] = 9;
+    let error = Replay::from_bytes(data).unwrap_err();
+    match error {
+        elma::ElmaError::Parse { offset: error_offset, ref location, ref error } => {
+            assert_eq!(error_offset, offset);
+            assert_eq!(location, "player 2 event #8");
+            assert_eq!(**error, elma::ElmaError::InvalidEvent(9));
+        },
+        _ => panic!("expected parse error, got {:?}", error)
+    }
+    assert!(format!("{}", error).contains("invalid event value 9"));
+}
+
+#[test]
+/// Frame flags are written back exactly, including the bits Elma uses for other data.
+fn replay_write_frame_flags () {
+    let mut replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    let mut bytes = vec![];
+    replay.write_to(&mut bytes).unwrap();
+    let frames_end = 36 + replay.frames.len() * 27;
+    assert_eq!(&bytes[..frames_end], &replay.raw[..frames_end]);
+    assert!(replay.frames[0].throttle());
+    assert!(!replay.frames[0].right());
+
+    replay.frames[0].set_right(true);
+    replay.frames[0].set_throttle(false);
+    assert_eq!(replay.frames[0].flags, 0xCE);
+    let mut bytes = vec![];
+    replay.write_to(&mut bytes).unwrap();
+    assert_eq!(Replay::from_bytes(bytes).unwrap().frames[0].flags, 0xCE);
+}
+
+/// Level with the objects of tutor14.lev, which test_1.rec and test_2.rec were recorded on.
+fn tutor14 (objects: Vec<ObjectType>) -> Level {
+    let mut level = Level::new();
+    level.link = 2549082363;
+    level.objects = objects.into_iter().map(|object_type| Object {
+        position: Position { x: 0., y: 0. },
+        object_type
+    }).collect();
+    level
+}
+
+fn apple () -> ObjectType {
+    ObjectType::Apple { gravity: Direction::Normal, animation: 1 }
+}
+
+#[test]
+fn replay_check_level () {
+    let level = tutor14(vec![ObjectType::Player, apple(), apple(), ObjectType::Exit]);
+    let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    assert_eq!(replay.check_level(&level), vec![]);
+    let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    assert_eq!(replay.check_level(&level), vec![]);
+
+    // Flower touched before taking all apples.
+    let level = tutor14(vec![ObjectType::Player, apple(), apple(), ObjectType::Exit, apple()]);
+    assert_eq!(replay.check_level(&level), vec![LevelMismatch::AppleCount(2, 3)]);
+
+    // Object 3 is missing, and the player can't be touched.
+    let mut level = tutor14(vec![apple(), ObjectType::Player, apple()]);
+    level.link = 1;
+    let event = replay.events.iter().position(|event| event.event_type == EventType::Touch { index: 3 }).unwrap();
+    let event_2 = replay.events_2.iter().position(|event| event.event_type == EventType::Touch { index: 3 }).unwrap();
+    let first = replay.events.iter().position(|event| event.event_type == EventType::Touch { index: 1 }).unwrap();
+    let first_2 = replay.events_2.iter().position(|event| event.event_type == EventType::Touch { index: 1 }).unwrap();
+    assert_eq!(replay.check_level(&level), vec![
+        LevelMismatch::Link(2549082363, 1),
+        LevelMismatch::InvalidTouch { player: Player::One, event: first, index: 1 },
+        LevelMismatch::InvalidTouch { player: Player::One, event, index: 3 },
+        LevelMismatch::InvalidTouch { player: Player::Two, event: first_2, index: 1 },
+        LevelMismatch::InvalidTouch { player: Player::Two, event: event_2, index: 3 }
+    ]);
+}
+
+#[test]
+fn replay_finish () {
+    let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    let level = tutor14(vec![ObjectType::Player, apple(), apple(), ObjectType::Exit]);
+    let finish = replay.finish(&level).unwrap();
+    assert_eq!(finish.player, Player::One);
+    assert_eq!(finish.event, replay.events.len() - 1);
+    assert_eq!(finish.time, Time::from_event_time(replay.events[finish.event].time));
+    assert_eq!(finish.flower, 3);
+    assert_eq!(finish.apples, 2);
+    assert!(finish.all_apples);
+
+    // Last touch is an apple.
+    let level = tutor14(vec![ObjectType::Player, apple(), ObjectType::Exit, apple()]);
+    assert_eq!(replay.finish(&level), None);
+    let replay = Replay::load("tests/assets/replays/unfinished.rec").unwrap();
+    assert_eq!(replay.finish(&level), None);
+}
+
+#[test]
+fn frame_state_round_trip () {
+    for file in ["tests/assets/replays/test_1.rec", "tests/assets/replays/test_2.rec"].iter() {
+        let replay = Replay::load(file).unwrap();
+        for frame in replay.frames.iter().chain(replay.frames_2.iter()) {
+            let mut encoded = Frame::new();
+            encoded.flags = frame.flags;
+            encoded.volume = frame.volume;
+            encoded.set_state(&frame.state());
+            assert_eq!(&encoded, frame);
+        }
+    }
+}
+
+#[test]
+fn frame_state_values () {
+    let replay = Replay::load("tests/assets/replays/test_1.rec").unwrap();
+    let state = replay.frames[100].state();
+    assert_eq!(state.bike, Position { x: 27.142517089844_f32 as f64, y: -1.1152113676071_f32 as f64 });
+    assert!((state.left_wheel.x - (state.bike.x - 0.903)).abs() < 1e-9);
+    assert!((state.right_wheel.y - (state.bike.y - 0.534)).abs() < 1e-9);
+    assert!((state.head.x - (state.bike.x + 0.074)).abs() < 1e-9);
+    assert!((state.rotation - 9826. / 10000. * 2. * std::f64::consts::PI).abs() < 1e-9);
+    assert!((state.left_wheel_rotation - 248. / 250. * 2. * std::f64::consts::PI).abs() < 1e-9);
+
+    // Angles that can't be stored are wrapped, and offsets are clamped.
+    let mut frame = Frame::new();
+    frame.set_state(&FrameState {
+        rotation: -7. * std::f64::consts::PI,
+        left_wheel_rotation: 5. * std::f64::consts::PI,
+        head: Position { x: 100., y: -0.25 },
+        ..FrameState::default()
+    });
+    assert_eq!(frame.rotation, 5000);
+    assert_eq!(frame.left_wheel_rotation, 125);
+    assert_eq!(frame.head, Position { x: i16::MAX, y: -250 });
+}
+
+#[test]
+/// Rotations outside one turn, like wheel rotations 250 to 255 and negative bike rotations,
+/// are kept when encoding them back.
+fn frame_state_round_trip_rotations () {
+    let mut frame = Frame::new();
+    for rotation in 0..=u8::MAX {
+        frame.left_wheel_rotation = rotation;
+        frame.right_wheel_rotation = u8::MAX - rotation;
+        let mut encoded = Frame::new();
+        encoded.set_state(&frame.state());
+        assert_eq!(encoded, frame);
+    }
+    for rotation in i16::MIN..=i16::MAX {
+        frame.rotation = rotation;
+        let mut encoded = Frame::new();
+        encoded.set_state(&frame.state());
+        assert_eq!(encoded.rotation, rotation);
+    }
+}
+
+#[test]
+fn replay_state_at () {
+    let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    let time = |frame: f64| frame * FRAME_DURATION / EVENT_TIME_SCALE;
+    for &player in [Player::One, Player::Two].iter() {
+        let frames = replay.frames_of(player);
+        assert_eq!(replay.state_at(player, 0.), Some(frames[0].state()));
+        let state = replay.state_at(player, time(100.)).unwrap();
+        assert!((state.bike.x - frames[100].state().bike.x).abs() < 1e-9);
+        let last = (frames.len() - 1) as f64;
+        assert!(replay.state_at(player, time(last - 0.01)).is_some());
+        assert_eq!(replay.state_at(player, time(last + 0.01)), None);
+        assert_eq!(replay.state_at(player, time(-0.01)), None);
+    }
+
+    // Halfway between frames 100 and 101.
+    let a = replay.frames[100].state();
+    let b = replay.frames[101].state();
+    let state = replay.state_at(Player::One, time(100.5)).unwrap();
+    assert!((state.bike.x - (a.bike.x + b.bike.x) / 2.).abs() < 1e-9);
+    assert!((state.head.y - (a.head.y + b.head.y) / 2.).abs() < 1e-9);
+}
+
+#[test]
+/// Sampling at the exact time of an event, without rounding to milliseconds.
+fn replay_state_at_event_time () {
+    let replay = Replay::load("tests/assets/replays/test_2.rec").unwrap();
+    let last = (replay.frames.len() - 1) as f64;
+    for event in replay.events.iter().filter(|event| event.frame() < last) {
+        let position = event.frame();
+        let index = position.floor() as usize;
+        let fraction = position.fract();
+        let (a, b) = (replay.frames[index].state(), replay.frames[index + 1].state());
+        let state = replay.state_at(Player::One, event.time).unwrap();
+        assert!((state.bike.x - (a.bike.x + (b.bike.x - a.bike.x) * fraction)).abs() < 1e-9);
+        assert!((state.head.y - (a.head.y + (b.head.y - a.head.y) * fraction)).abs() < 1e-9);
+    }
+}
+
+#[test]
+/// Rotations interpolate the shorter way around a full turn.
+fn replay_state_at_wrap_around () {
+    let mut replay = Replay::new();
+    let mut frame = Frame::new();
+    frame.rotation = 9900;
+    frame.left_wheel_rotation = 240;
+    replay.frames.push(frame);
+    let mut frame = Frame::new();
+    frame.rotation = 100;
+    frame.left_wheel_rotation = 10;
+    replay.frames.push(frame);
+
+    let state = replay.state_at(Player::One, 0.45 * FRAME_DURATION / EVENT_TIME_SCALE).unwrap();
+    let turns = state.rotation / (2. * std::f64::consts::PI);
+    assert!(turns > 0.99 && turns < 1., "{}", turns);
+    let turns = state.left_wheel_rotation / (2. * std::f64::consts::PI);
+    assert!(!(0.01..=0.99).contains(&turns), "{}", turns);
+    assert!(state.left_wheel_rotation >= 0.);
 }
@@ -0,0 +1,136 @@
+extern crate elma;
+
+use elma::lev::ListEntry;
+use elma::time::Time;
+use elma::state::*;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+#[test]
+fn state_default_values () {
+    let state = State::new();
+    assert_eq!(state.times.len(), 90);
+    assert_eq!(state.times[0], BestTimes { single: vec![], multi: vec![] });
+    assert_eq!(state.players.len(), 0);
+    assert_eq!(State::default(), state);
+}
+
+#[test]
+/// Build a state with some arbitrary values, save and load it again.
+fn construct_state_save_and_load () {
+    let mut state = State::new();
+    state.times[0].single.push(ListEntry { time: Time::from_hundredths(1464), name_1: "Rust".to_string(), name_2: "Cargo".to_string() });
+    state.times[0].single.push(ListEntry { time: Time::from_hundredths(1500), name_1: "Cargo".to_string(), name_2: "Rust".to_string() });
+    state.times[53].multi.push(ListEntry { time: Time::from_hundredths(9999), name_1: "Rust".to_string(), name_2: "Cargo".to_string() });
+    let mut player = PlayerEntry::new("Rust");
+    player.skipped[3] = true;
+    player.levels_reached = 12;
+    player.selected_level = 11;
+    state.players.push(player);
+    state.players.push(PlayerEntry::new("Cargo"));
+    state.player_a_name = "Rust".to_string();
+    state.player_b_name = "Cargo".to_string();
+    state.sound = false;
+    state.sound_optimization = SoundOptimization::Compatibility;
+    state.play_mode = PlayMode::Multi;
+    state.flag_tag = true;
+    state.swap_bikes = true;
+    state.video_detail = VideoDetail::Low;
+    state.animated_objects = false;
+    state.player_a_keys.throttle = 200;
+    state.player_b_keys.toggle_show_hide = 3;
+    state.screenshot_key = 23;
+    state.last_edited_level = "test_1.lev".to_string();
+    state.last_played_external = "test_2.lev".to_string();
+
+    let mut dir = env::temp_dir();
+    dir.push("construct_state.dat");
+    state.save(&dir).unwrap();
+    let state_saved = State::load(&dir).unwrap();
+    assert_eq!(state, state_saved);
+}
+
+#[test]
+/// The file on disk should be encrypted, and decrypt back to `raw`.
+fn save_state_encrypted () {
+    let mut state = State::new();
+    state.players.push(PlayerEntry::new("Rust"));
+    let mut dir = env::temp_dir();
+    dir.push("encrypted_state.dat");
+    state.save(&dir).unwrap();
+
+    let mut encrypted = vec![];
+    File::open(&dir).unwrap().read_to_end(&mut encrypted).unwrap();
+    assert_eq!(encrypted.len(), state.raw.len());
+    assert!(encrypted[0..4] != state.raw[0..4]);
+    crypt_state(&mut encrypted);
+    assert_eq!(encrypted, state.raw);
+}
+
+#[test]
+/// Only 50 players fit in state.dat.
+fn save_state_player_overflow () {
+    let mut state = State::new();
+    for n in 0..55 {
+        state.players.push(PlayerEntry::new(&format!("Player{}", n)));
+    }
+    let mut dir = env::temp_dir();
+    dir.push("player_overflow_state.dat");
+    state.save(&dir).unwrap();
+    let state_saved = State::load(&dir).unwrap();
+    assert_eq!(state_saved.players.len(), 50);
+    assert_eq!(state_saved.players[49].name, "Player49");
+}
+
+#[test]
+/// constructed.dat was written by a separate encoder following the state.dat format, not by
+/// this crate, so it checks the encryption keys and field layout independently.
+fn load_constructed_state_and_save () {
+    let mut state = State::load("tests/assets/state/constructed.dat").unwrap();
+    assert_eq!(state.times[0].single.len(), 3);
+    assert_eq!(state.times[0].single[1], ListEntry { time: Time::from_hundredths(1502), name_1: "Cargo".to_string(), name_2: "Cargo".to_string() });
+    assert_eq!(state.times[1].multi[0].time, Time::from_hundredths(2011));
+    assert_eq!(state.times[53].multi[0].name_1, "Cargo");
+    assert_eq!(state.players.len(), 2);
+    assert!(state.players[0].skipped[3] && state.players[0].skipped[17] && !state.players[0].skipped[4]);
+    assert_eq!(state.players[0].levels_reached, 21);
+    assert_eq!(state.players[1].selected_level, 1);
+    assert_eq!(state.player_b_name, "Cargo");
+    assert_eq!(state.play_mode, PlayMode::Single);
+    assert!(!state.swap_bikes);
+    assert_eq!(state.video_detail, VideoDetail::High);
+    assert_eq!(state.player_a_keys.throttle, 200);
+    assert_eq!(state.player_b_keys.toggle_show_hide, 3);
+    assert_eq!(state.screenshot_key, 23);
+    assert_eq!(state.last_played_external, "test_2.lev");
+
+    let mut original = vec![];
+    File::open("tests/assets/state/constructed.dat").unwrap().read_to_end(&mut original).unwrap();
+    let mut dir = env::temp_dir();
+    dir.push("constructed_state.dat");
+    state.save(&dir).unwrap();
+    let mut saved = vec![];
+    File::open(&dir).unwrap().read_to_end(&mut saved).unwrap();
+    assert_eq!(saved, original);
+}
+
+#[test]
+/// `from_bytes` and `write_to` use the encrypted file data, while `raw` stays decrypted.
+fn state_from_reader_and_write_to () {
+    let mut original = vec![];
+    File::open("tests/assets/state/constructed.dat").unwrap().read_to_end(&mut original).unwrap();
+    let mut state = State::from_bytes(&original[..]).unwrap();
+    assert_eq!(state, State::from_reader(std::io::Cursor::new(&original)).unwrap());
+    assert_eq!(state, State::load("tests/assets/state/constructed.dat").unwrap());
+    let mut bytes = vec![];
+    state.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes, original);
+    assert_ne!(state.raw, original);
+    assert_eq!(State::from_bytes(vec![]).unwrap_err(), elma::ElmaError::UnexpectedEnd);
+}
+
+#[test]
+fn load_invalid_state () {
+    assert_eq!(State::load("tests/assets/state/invalid_version.dat").unwrap_err(), elma::ElmaError::InvalidStateFile);
+}
@@ -0,0 +1,47 @@
+extern crate elma;
+
+use elma::ElmaError;
+use elma::time::Time;
+
+#[test]
+fn time_conversions () {
+    assert_eq!(Time::from_hundredths(1464), Time::from_ms(14640));
+    assert_eq!(Time::from_ms(14649).hundredths(), 1464);
+    assert_eq!(Time::from_frames(30), Time::from_ms(1000));
+    assert_eq!(Time::from_frames(440).frames(), 440);
+    assert_eq!(Time::from_event_time(1.), Time::from_ms(2289));
+    assert_eq!(Time::from_event_time(Time::from_ms(14649).event_time()), Time::from_ms(14649));
+}
+
+#[test]
+fn time_display () {
+    assert_eq!(Time::from_hundredths(0).to_string(), "00:00,00");
+    assert_eq!(Time::from_hundredths(2039).to_string(), "00:20,39");
+    assert_eq!(Time::from_hundredths(6000).to_string(), "01:00,00");
+    assert_eq!(Time::from_ms(14649).to_string(), "00:14,64");
+    assert_eq!(Time::from_hundredths(359_999).to_string(), "59:59,99");
+    assert_eq!(Time::from_hundredths(360_000).to_string(), "59:59,99");
+    assert_eq!(Time::from_ms(-10).to_string(), "00:00,00");
+}
+
+#[test]
+fn time_parse () {
+    assert_eq!("00:20,39".parse(), Ok(Time::from_hundredths(2039)));
+    assert_eq!("59:59,99".parse(), Ok(Time::from_hundredths(359_999)));
+    for invalid in ["", "00:60,00", "60:00,00", "0:20,39", "00:20.39", "00:20,3x", "+0:20,39"].iter() {
+        assert_eq!(invalid.parse::<Time>(), Err(ElmaError::InvalidTimeFormat), "{}", invalid);
+    }
+}
+
+#[test]
+fn time_arithmetic_and_ordering () {
+    let mut time = Time::from_hundredths(100) + Time::from_ms(5);
+    assert_eq!(time, Time::from_ms(1005));
+    time -= Time::from_ms(1000);
+    assert_eq!(time - Time::from_ms(10), Time::from_ms(-5));
+    time += Time::from_ms(1);
+    assert_eq!(time, Time::from_ms(6));
+    let mut times = vec![Time::from_ms(3), Time::from_ms(-1), Time::from_ms(2)];
+    times.sort();
+    assert_eq!(times, vec![Time::from_ms(-1), Time::from_ms(2), Time::from_ms(3)]);
+}
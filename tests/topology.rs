@@ -2,6 +2,7 @@ extern crate elma;
 
 use elma::{ Position };
 use elma::lev::*;
+use elma::lgr::{ Lgr, PictureData, PictureType };
 
 #[test]
 fn topology_ok() {
@@ -24,7 +25,7 @@ fn topology_ok() {
         }
     ];
 
-    assert_eq!(level.check_topology().is_ok(), true);
+    assert!(level.check_topology().is_ok());
 }
 
 #[test]
@@ -127,5 +128,240 @@ fn topology_err_invalid_vertex_count() {
     assert_eq!(level.check_topology().unwrap_err(), TopologyError::InvalidVertexCount(vec![1, 3]));
 }
 
-// TODO: AppleInsideGround(usize)
-// TODO: IntersectingPolygons
+fn test_lgr () -> Lgr {
+    let mut lgr = Lgr::new();
+    lgr.pictures.push(PictureData::new("barrel"));
+    let mut texture = PictureData::new("stone1");
+    texture.picture_type = PictureType::Texture;
+    lgr.pictures.push(texture);
+    let mut mask = PictureData::new("maskbig");
+    mask.picture_type = PictureType::Mask;
+    lgr.pictures.push(mask);
+    lgr
+}
+
+fn texture (texture: &str, mask: &str) -> Picture {
+    let mut picture = Picture::new();
+    picture.name = String::new();
+    picture.texture = String::from(texture);
+    picture.mask = String::from(mask);
+    picture
+}
+
+#[test]
+fn topology_pictures_ok() {
+    let mut level = Level::new();
+    level.pictures.push(Picture::new());
+    level.pictures.push(texture("Stone1", "maskbig"));
+    assert!(level.check_pictures(&test_lgr()).is_ok());
+    assert!(level.check_topology_with_lgr(&test_lgr()).is_ok());
+}
+
+#[test]
+fn topology_err_unknown_pictures() {
+    let mut level = Level::new();
+    level.pictures.push(Picture::new());
+    let mut picture = Picture::new();
+    picture.name = String::from("barel");
+    level.pictures.push(picture);
+    // Mask used as a picture name.
+    let mut picture = Picture::new();
+    picture.name = String::from("maskbig");
+    level.pictures.push(picture);
+    level.pictures.push(texture("stone2", "maskbig"));
+    level.pictures.push(texture("stone1", "mask"));
+    level.pictures.push(texture("", ""));
+    assert_eq!(level.check_pictures(&test_lgr()).unwrap_err(), TopologyError::UnknownPictures(vec![1, 2, 3, 4, 5]));
+}
+
+#[test]
+fn topology_err_missing_mask() {
+    let mut level = Level::new();
+    level.pictures.push(texture("stone1", "maskbig"));
+    level.pictures.push(texture("stone1", ""));
+    assert_eq!(level.check_topology_with_lgr(&test_lgr()).unwrap_err(), TopologyError::MissingMask(vec![1]));
+}
+
+#[test]
+fn topology_err_invalid_picture_distance() {
+    let mut level = Level::new();
+    level.pictures.push(Picture::new());
+    level.pictures[0].distance = 0;
+    level.pictures.push(Picture::new());
+    level.pictures[1].distance = 999;
+    level.pictures.push(Picture::new());
+    level.pictures[2].distance = 1000;
+    assert_eq!(level.check_pictures(&test_lgr()).unwrap_err(), TopologyError::InvalidPictureDistance(vec![0, 2]));
+    assert_eq!(level.check_topology_with_lgr(&test_lgr()).unwrap_err(), TopologyError::InvalidPictureDistance(vec![0, 2]));
+    let report = level.topology_report(Some(&test_lgr()));
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].severity, Severity::Error);
+}
+
+#[test]
+fn topology_head_ok() {
+    let level = Level::load("tests/assets/levels/start_topology_1.lev").unwrap();
+    assert!(level.check_topology().is_ok());
+}
+
+#[test]
+/// The editor saved this level without errors, so the head is only a warning.
+fn topology_warn_head_inside_ground() {
+    let level = Level::load("tests/assets/levels/start_topology_2.lev").unwrap();
+    assert!(level.check_topology().is_ok());
+    let report = level.topology_report(None);
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].error, TopologyError::HeadInsideGround);
+    assert_eq!(report[0].severity, Severity::Warning);
+}
+
+#[test]
+/// Apple 2 is fully inside ground, apple 3 only partially. The editor saved this level without
+/// errors, so apples are only a warning.
+fn topology_warn_apple_inside_ground() {
+    let level = Level::load("tests/assets/levels/objects_topology.lev").unwrap();
+    assert!(!level.is_ground(&level.objects[0].position));
+    assert!(level.is_ground(&level.objects[4].position));
+    // Inside a polygon nested two levels deep.
+    assert!(!level.is_ground(&level.objects[5].position));
+    assert!(level.check_topology().is_ok());
+    let report = level.topology_report(None);
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].error, TopologyError::AppleInsideGround(vec![2]));
+    assert_eq!(report[0].severity, Severity::Warning);
+}
+
+#[test]
+fn topology_err_self_intersecting_polygon() {
+    let level = Level::load("tests/assets/levels/topology_intersect_1.lev").unwrap();
+    assert_eq!(level.check_topology().unwrap_err(), TopologyError::IntersectingPolygons(vec![0]));
+}
+
+#[test]
+fn topology_err_intersecting_polygons() {
+    let level = Level::load("tests/assets/levels/topology_intersect_2.lev").unwrap();
+    assert_eq!(level.check_topology().unwrap_err(), TopologyError::IntersectingPolygons(vec![0, 1]));
+}
+
+#[test]
+/// Grass polygons are allowed to intersect ground.
+fn topology_intersecting_grass_ok() {
+    let level = Level::load("tests/assets/levels/topology_intersect_3.lev").unwrap();
+    assert!(level.check_topology().is_ok());
+}
+
+#[test]
+fn topology_err_intersecting_polygons_many() {
+    let mut level = Level::new();
+    // Grid of 1000 small squares, with one crossing its neighbour.
+    level.polygons = vec![];
+    for n in 0..1000 {
+        let x = (n % 40) as f64 * 4_f64;
+        let y = (n / 40) as f64 * 4_f64;
+        level.polygons.push(Polygon {
+            grass: false,
+            vertices: vec![
+                Position { x, y },
+                Position { x: x + 2_f64, y },
+                Position { x: x + 2_f64, y: y + 2_f64 },
+                Position { x, y: y + 2_f64 }]
+        });
+    }
+    level.polygons[0].vertices[1].x = 5_f64;
+    assert_eq!(level.check_topology().unwrap_err(), TopologyError::IntersectingPolygons(vec![0, 1]));
+}
+
+#[test]
+/// Long edges spanning most of the level, which would fill most grid cells each.
+fn topology_err_intersecting_polygons_long_edges() {
+    let mut level = Level::new();
+    // Thin parallel strips going diagonally across the level.
+    level.polygons = vec![];
+    for n in 0..2000 {
+        let x = n as f64;
+        level.polygons.push(Polygon {
+            grass: false,
+            vertices: vec![
+                Position { x, y: 0_f64 },
+                Position { x: x + 0.5, y: 0_f64 },
+                Position { x: x + 1000.5, y: 1000_f64 },
+                Position { x: x + 1000_f64, y: 1000_f64 }]
+        });
+    }
+    let findings = level.topology_report(None);
+    assert!(!findings.iter().any(|finding| matches!(finding.error, TopologyError::IntersectingPolygons(_))));
+
+    // Lean one strip into the next.
+    level.polygons[1000].vertices[2].x += 1.2;
+    let findings = level.topology_report(None);
+    let intersecting = findings.iter().find(|finding| matches!(finding.error, TopologyError::IntersectingPolygons(_))).unwrap();
+    assert_eq!(intersecting.error, TopologyError::IntersectingPolygons(vec![1000, 1001]));
+}
+
+#[test]
+fn topology_report_all_findings() {
+    let mut level = Level::load("tests/assets/levels/topology_intersect_2.lev").unwrap();
+    level.objects.push(Object::new());
+    level.objects[2].position = Position { x: 30_f64, y: 30_f64 };
+    level.objects.push(Object::new());
+    level.objects[3].object_type = ObjectType::Player;
+    level.pictures.push(Picture::new());
+    level.pictures[0].distance = 1000;
+    level.pictures.push(Picture::new());
+    level.pictures[1].name = String::from("barel");
+    level.pictures[1].position = Position { x: 1_f64, y: 2_f64 };
+
+    let report = level.topology_report(Some(&test_lgr()));
+    let errors: Vec<_> = report.iter().map(|finding| &finding.error).collect();
+    assert_eq!(errors, vec![&TopologyError::InvalidPlayerCount(2),
+                            &TopologyError::IntersectingPolygons(vec![0, 1]),
+                            &TopologyError::HeadInsideGround,
+                            &TopologyError::AppleInsideGround(vec![2]),
+                            &TopologyError::UnknownPictures(vec![1]),
+                            &TopologyError::InvalidPictureDistance(vec![0])]);
+    assert_eq!(report[0].locations, vec![Location::Object(0, level.objects[0].position),
+                                         Location::Object(3, Position { x: 0_f64, y: 0_f64 })]);
+    assert_eq!(report[1].locations.len(), 3);
+    assert_eq!(report[1].locations[0], Location::Vertex(0, 0, level.polygons[0].vertices[0]));
+    // Only the second player starts in ground.
+    assert_eq!(report[2].locations, vec![Location::Object(3, Position { x: 0_f64, y: 0_f64 })]);
+    assert_eq!(report[2].severity, Severity::Warning);
+    assert_eq!(report[3].severity, Severity::Warning);
+    assert_eq!(report[4].locations, vec![Location::Picture(1, Position { x: 1_f64, y: 2_f64 })]);
+    assert_eq!(report[4].severity, Severity::Error);
+    assert_eq!(report[5].severity, Severity::Error);
+
+    assert_eq!(level.check_topology().unwrap_err(), TopologyError::InvalidPlayerCount(2));
+}
+
+#[test]
+/// Warnings don't fail `check_topology_with_lgr`.
+fn topology_report_warnings_only() {
+    let mut level = Level::load("tests/assets/levels/objects_topology.lev").unwrap();
+    level.pictures.push(Picture::new());
+    let report = level.topology_report(Some(&test_lgr()));
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].severity, Severity::Warning);
+    assert!(level.check_topology_with_lgr(&test_lgr()).is_ok());
+}
+
+#[test]
+/// Level away from the origin, just within the limits.
+fn topology_size_away_from_origin() {
+    let mut level = Level::new();
+    for polygon in &mut level.polygons {
+        for vertex in &mut polygon.vertices {
+            vertex.x += 500_f64;
+            vertex.y -= 300_f64;
+        }
+    }
+    for object in &mut level.objects {
+        object.position.x += 500_f64;
+        object.position.y -= 300_f64;
+    }
+    level.polygons[0].vertices[0].x = 500_f64 + 188_f64;
+    level.polygons[0].vertices[1].x = 500_f64 + 188_f64;
+    assert!(level.check_topology().is_ok());
+    level.polygons[0].vertices[2].y = -300_f64 + 189_f64;
+    assert_eq!(level.check_topology().unwrap_err(), TopologyError::TooHigh(1_f64));
+}
@@ -43,3 +43,20 @@ fn invalid_time_format_1 () {
 fn invalid_time_format_2 () {
     assert_eq!(time_format(601039_i32).unwrap_err(), elma::ElmaError::InvalidTimeFormat);
 }
+
+#[test]
+fn error_display () {
+    use std::error::Error;
+    let error = elma::ElmaError::Parse { offset: 1522, location: String::from("object #10"),
+                                         error: Box::new(elma::ElmaError::InvalidObject(6)) };
+    assert_eq!(format!("{}", error), "object #10 at offset 1522 (0x5F2): invalid object value 6");
+    assert_eq!(error.source().unwrap().to_string(), "invalid object value 6");
+    assert_eq!(error.root_cause(), &elma::ElmaError::InvalidObject(6));
+    let topology = elma::lev::TopologyError::IntersectingPolygons(vec![1, 2]);
+    assert_eq!(format!("{}", topology), "intersecting polygons: [1, 2]");
+}
+
+#[test]
+fn invalid_time_format_negative () {
+    assert_eq!(time_format(-5).unwrap_err(), elma::ElmaError::InvalidTimeFormat);
+}